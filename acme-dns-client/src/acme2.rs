@@ -0,0 +1,72 @@
+//! DNS-01 glue for the [`acme2`](https://docs.rs/acme2) crate.
+//!
+//! Enabled with the `acme2` feature. The TXT value is derived from the
+//! challenge token and the account key thumbprint as RFC 8555 §8.4 describes.
+//!
+//! ```no_run
+//! # async fn example(
+//! #     client: &acme_dns_client::AcmeDnsClient,
+//! #     creds: &acme_dns_client::Credentials,
+//! #     challenge: acme2::Challenge,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use acme_dns_client::acme2::set_challenge;
+//!
+//! set_challenge(client, creds, &challenge).await?;
+//! let challenge = challenge.validate().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{AcmeDnsClient, Credentials, Error, dns01_txt_value};
+
+/// Publish the DNS-01 TXT value for an `acme2` challenge.
+///
+/// Fails with [`Error::Acme`] if the challenge carries no token.
+pub async fn set_challenge(
+    client: &AcmeDnsClient,
+    creds: &Credentials,
+    challenge: &::acme2::Challenge,
+) -> Result<(), Error> {
+    let key_authorization = challenge
+        .key_authorization()
+        .map_err(|e| Error::Acme(e.to_string()))?
+        .ok_or_else(|| Error::Acme("challenge has no token".to_string()))?;
+
+    client
+        .update_txt_for_key_auth(creds, &key_authorization)
+        .await
+}
+
+/// Publish the DNS-01 TXT value for `token` and the account key `thumbprint`.
+pub async fn set_dns01_challenge(
+    client: &AcmeDnsClient,
+    creds: &Credentials,
+    token: &str,
+    thumbprint: &str,
+) -> Result<(), Error> {
+    client
+        .update_txt(creds, &txt_value(token, thumbprint))
+        .await
+}
+
+/// The DNS-01 TXT value for `token` and the account key `thumbprint`.
+pub fn txt_value(token: &str, thumbprint: &str) -> String {
+    dns01_txt_value(&format!("{token}.{thumbprint}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn txt_value_from_token_and_thumbprint() {
+        // Token from RFC 8555 §8.4, thumbprint from RFC 7638 §3.1.
+        assert_eq!(
+            txt_value(
+                "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA",
+                "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+            ),
+            "ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll8"
+        );
+    }
+}
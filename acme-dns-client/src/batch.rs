@@ -0,0 +1,141 @@
+//! Updating many accounts at once.
+
+use std::fmt;
+
+use futures_util::{StreamExt, stream};
+use thiserror::Error;
+
+use crate::{AcmeDnsClient, Credentials, Error};
+
+/// Counts from [`AcmeDnsClient::update_many`]; displays as e.g.
+/// `38/40 updated`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BatchOutcome {
+    /// Updates the server accepted.
+    pub updated: usize,
+    /// Updates attempted.
+    pub total: usize,
+}
+
+impl BatchOutcome {
+    /// Updates that failed.
+    pub fn failed(&self) -> usize {
+        self.total - self.updated
+    }
+}
+
+impl fmt::Display for BatchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} updated", self.updated, self.total)
+    }
+}
+
+/// [`AcmeDnsClient::update_many`] where some updates failed: the counts,
+/// plus each failed account's subdomain and error, in input order.
+#[derive(Debug, Error)]
+#[error("{outcome}, {} failed: {}", .failures.len(), join_failures(.failures))]
+#[non_exhaustive]
+pub struct BatchError {
+    /// How many of the updates succeeded.
+    pub outcome: BatchOutcome,
+    /// The subdomain and error of each failed update.
+    pub failures: Vec<(String, Error)>,
+}
+
+fn join_failures(failures: &[(String, Error)]) -> String {
+    failures
+        .iter()
+        .map(|(subdomain, e)| format!("{subdomain}: {e}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl AcmeDnsClient {
+    /// Set a TXT value on each of several accounts, running up to
+    /// `concurrency` updates at a time.
+    ///
+    /// Every update is attempted. If any fail, the result is a
+    /// [`BatchError`] naming them; the others still took effect.
+    pub async fn update_many(
+        &self,
+        updates: &[(&Credentials, &str)],
+        concurrency: usize,
+    ) -> Result<BatchOutcome, BatchError> {
+        let results: Vec<_> = stream::iter(updates)
+            .map(|(creds, txt)| async move { (creds, self.update_txt(creds, txt).await) })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let failures: Vec<_> = results
+            .into_iter()
+            .filter_map(|(creds, result)| result.err().map(|e| (creds.subdomain.clone(), e)))
+            .collect();
+        let outcome = BatchOutcome {
+            updated: updates.len() - failures.len(),
+            total: updates.len(),
+        };
+        if failures.is_empty() {
+            Ok(outcome)
+        } else {
+            Err(BatchError { outcome, failures })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn creds(subdomain: &str) -> Credentials {
+        Credentials::builder()
+            .username("user")
+            .password("pw")
+            .subdomain(subdomain)
+            .fulldomain(format!("{subdomain}.auth.example.org"))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn update_many_reports_partial_success() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .body_contains(r#""subdomain":"bad""#);
+            then.status(400).body(r#"{"error": "bad_subdomain"}"#);
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let accounts = [creds("a"), creds("bad"), creds("c")];
+        let updates: Vec<_> = accounts.iter().map(|c| (c, "token")).collect();
+        let err = client.update_many(&updates, 2).await.unwrap_err();
+
+        assert_eq!(err.outcome.to_string(), "2/3 updated");
+        assert_eq!(err.outcome.failed(), 1);
+        assert_eq!(err.failures.len(), 1);
+        assert_eq!(err.failures[0].0, "bad");
+        assert!(
+            matches!(err.failures[0].1, Error::UnexpectedStatus { .. }),
+            "{:?}",
+            err.failures[0].1
+        );
+        assert!(err.to_string().starts_with("2/3 updated, 1 failed: bad: "));
+
+        let ok = client.update_many(&updates[..1], 2).await.unwrap();
+        assert_eq!(
+            ok,
+            BatchOutcome {
+                updated: 1,
+                total: 1
+            }
+        );
+    }
+}
@@ -1,13 +1,28 @@
-use acme_dns_client::{AcmeDnsClient, Credentials};
-use clap::{Parser, Subcommand};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use acme_dns_client::{
+    AcmeDnsClient, Credentials, Error, PropagationOptions, challenge_fqdn, dns01_txt_value,
+    verify_txt_matches,
+};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use serde_json::{Value, json};
 
 #[derive(Parser, Debug)]
 #[command(name = "acme-dns-cli")]
 #[command(about = "Tiny CLI to test an acme-dns server")]
 struct Cli {
     /// Base URL of the acme-dns API, e.g. https://auth.example.org/
+    /// (not needed for verify-txt).
     #[arg(long, env = "ACME_DNS_API_BASE")]
-    api_base: String,
+    api_base: Option<String>,
+
+    /// Print results (and errors) as JSON on stdout, for scripting.
+    #[arg(long, global = true)]
+    json: bool,
 
     #[command(subcommand)]
     command: Command,
@@ -20,46 +35,512 @@ enum Command {
         /// CIDR networks allowed to call /update (comma-separated or repeated).
         #[arg(long, value_delimiter = ',')]
         allowfrom: Option<Vec<String>>,
+
+        /// Domain you want certificates for; prints the CNAME record to
+        /// create for it (on stderr, so stdout stays valid JSON).
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Print `export ACME_DNS_...=...` lines (shell-quoted, for `eval`)
+        /// instead of JSON.
+        #[arg(long)]
+        emit_env: bool,
     },
 
-    /// Call /update using credentials from environment.
+    /// Call /update using credentials from environment or a JSON file.
     ///
-    /// Uses ACME_DNS_USERNAME, ACME_DNS_PASSWORD, ACME_DNS_SUBDOMAIN,
-    /// ACME_DNS_FULLDOMAIN for credentials, and ACME_DNS_ALLOWFROM optional.
+    /// Without --creds, uses ACME_DNS_USERNAME, ACME_DNS_PASSWORD,
+    /// ACME_DNS_SUBDOMAIN, ACME_DNS_FULLDOMAIN for credentials, and
+    /// ACME_DNS_ALLOWFROM optional.
     Update {
         /// TXT value to set for the challenge.
         #[arg(long)]
         txt: String,
+
+        /// Load credentials from this JSON file (as printed by `register`)
+        /// instead of the environment.
+        #[arg(long)]
+        creds: Option<PathBuf>,
     },
 
     /// Call /health and print result.
     Health,
+
+    /// Print the API base and credentials the other commands would use,
+    /// with the password redacted.
+    Config {
+        /// Show credentials from this JSON file instead of the environment,
+        /// as `update --creds` would.
+        #[arg(long)]
+        creds: Option<PathBuf>,
+    },
+
+    /// Check offline that a TXT value is the DNS-01 digest of a key
+    /// authorization. Exits non-zero on a mismatch.
+    VerifyTxt {
+        /// Key authorization (`<token>.<thumbprint>`) the CA computed.
+        #[arg(long)]
+        key_auth: String,
+
+        /// TXT value observed in DNS.
+        #[arg(long)]
+        txt: String,
+    },
+
+    /// Check a freshly deployed server end to end: register a throwaway
+    /// account, set a TXT value and check that it resolves, both at the
+    /// account's fulldomain and through the CNAME for --domain. Prints
+    /// each step and exits non-zero on the first failure.
+    Selftest {
+        /// Domain whose `_acme-challenge` CNAME you will point at the new
+        /// account.
+        #[arg(long)]
+        domain: String,
+
+        /// Don't wait for Enter after printing the CNAME record, e.g. when
+        /// the domain's DNS is updated by a script.
+        #[arg(long)]
+        yes: bool,
+
+        /// Seconds to wait for each DNS lookup to see the TXT value.
+        #[arg(long, default_value_t = 120)]
+        timeout: u64,
+    },
+
+    /// Print a shell completion script, e.g.
+    /// `acme-dns-cli completions bash > /etc/bash_completion.d/acme-dns-cli`.
+    Completions {
+        /// Shell to generate completions for.
+        shell: Shell,
+    },
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    let json = cli.json;
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if json => {
+            println!("{}", error_json(&err));
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let client = AcmeDnsClient::new(&cli.api_base)?;
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    let api_base = cli.api_base.as_deref();
+    let client = || match api_base {
+        Some(base) => Ok(AcmeDnsClient::new(base)?),
+        None => Err(anyhow::anyhow!(
+            "--api-base or ACME_DNS_API_BASE is required"
+        )),
+    };
 
     match cli.command {
-        Command::Register { allowfrom } => {
-            let allow_ref = allowfrom.as_ref().map(|v| v.as_slice());
-            let creds = client.register(allow_ref).await?;
-            println!("{}", serde_json::to_string_pretty(&creds)?);
+        Command::Register {
+            allowfrom,
+            domain,
+            emit_env,
+        } => {
+            let creds = client()?.register(allowfrom.as_deref()).await?;
+            if emit_env {
+                print!("{}", creds.to_env_exports());
+            } else {
+                println!("{}", serde_json::to_string_pretty(&creds)?);
+            }
+            if let Some(domain) = domain {
+                eprintln!(
+                    "Create this DNS record:\n  {}",
+                    creds.cname_instructions(&domain)
+                );
+            }
         }
 
-        Command::Update { txt } => {
-            let creds = Credentials::from_env()?;
-            client.update_txt(&creds, &txt).await?;
-            println!("update OK for {}", creds.fulldomain);
+        Command::Update { txt, creds } => {
+            let creds = match creds {
+                Some(path) => Credentials::load_from_path(path)?,
+                None => Credentials::from_env()?,
+            };
+            client()?.update_txt(&creds, &txt).await?;
+            if cli.json {
+                println!("{}", ok_json(Some(&creds.fulldomain)));
+            } else {
+                println!("update OK for {}", creds.fulldomain);
+            }
         }
 
         Command::Health => {
-            client.health().await?;
-            println!("health OK");
+            client()?.health().await?;
+            if cli.json {
+                println!("{}", ok_json(None));
+            } else {
+                println!("health OK");
+            }
+        }
+
+        Command::Config { creds } => {
+            let source = match &creds {
+                Some(path) => format!("file {}", path.display()),
+                None => "environment (ACME_DNS_*)".to_string(),
+            };
+            let loaded = match &creds {
+                Some(path) => Credentials::load_from_path(path),
+                None => Credentials::from_env(),
+            };
+            if cli.json {
+                println!("{}", config_json(api_base, &source, &loaded));
+            } else {
+                println!("api_base: {}", api_base.unwrap_or("(not set)"));
+                println!("credentials: {source}");
+                match &loaded {
+                    Ok(creds) => println!("  {}", creds.redacted()),
+                    Err(e) => println!("  not loaded: {e}"),
+                }
+            }
+        }
+
+        Command::VerifyTxt { key_auth, txt } => {
+            let expected = dns01_txt_value(&key_auth);
+            if !verify_txt_matches(&key_auth, &txt) {
+                anyhow::bail!("TXT mismatch: expected {expected}, got {txt}");
+            }
+            if cli.json {
+                println!("{}", json!({ "status": "ok", "expected": expected }));
+            } else {
+                println!("TXT OK: {expected}");
+            }
+        }
+
+        Command::Selftest {
+            domain,
+            yes,
+            timeout,
+        } => {
+            let propagation = PropagationOptions::new().timeout(Duration::from_secs(timeout));
+            let confirm = || {
+                if yes {
+                    return Ok(());
+                }
+                eprintln!("Press Enter once the record is in place...");
+                io::stdin().lock().read_line(&mut String::new())?;
+                Ok(())
+            };
+            selftest(&client()?, &domain, propagation, confirm, &mut io::stdout()).await?;
+        }
+
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "acme-dns-cli",
+                &mut std::io::stdout(),
+            );
         }
     }
 
     Ok(())
 }
+
+/// Run the `selftest` steps, writing one line per step to `out`.
+/// `confirm` is called once the CNAME record has been printed.
+async fn selftest(
+    client: &AcmeDnsClient,
+    domain: &str,
+    propagation: PropagationOptions,
+    confirm: impl FnOnce() -> io::Result<()>,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    client.health().await.map_err(|e| fail(out, "health", e))?;
+    writeln!(out, "ok   health")?;
+
+    let creds = client
+        .register(None)
+        .await
+        .map_err(|e| fail(out, "register", e))?;
+    writeln!(out, "ok   register: {}", creds.fulldomain)?;
+    writeln!(out, "     create: {}", creds.cname_instructions(domain))?;
+    out.flush()?;
+    confirm()?;
+
+    let txt = dns01_txt_value(&format!("acme-dns-cli selftest {}", creds.subdomain));
+    client
+        .update_txt(&creds, &txt)
+        .await
+        .map_err(|e| fail(out, "update", e))?;
+    writeln!(out, "ok   update: {txt}")?;
+
+    client
+        .wait_for_propagation(&creds, &txt, &propagation)
+        .await
+        .map_err(|e| fail(out, "resolve", e))?;
+    writeln!(out, "ok   resolve: {}", creds.fulldomain)?;
+
+    // Look the value up again through the CNAME, as the CA will.
+    let via_cname = Credentials {
+        fulldomain: challenge_fqdn(domain),
+        ..creds
+    };
+    client
+        .wait_for_propagation(&via_cname, &txt, &propagation)
+        .await
+        .map_err(|e| fail(out, "resolve via CNAME", e))?;
+    writeln!(out, "ok   resolve via CNAME: {}", via_cname.fulldomain)?;
+
+    Ok(())
+}
+
+/// Write a failed `selftest` step to `out` and hand back its error.
+fn fail(out: &mut impl Write, step: &str, err: Error) -> anyhow::Error {
+    let _ = writeln!(out, "FAIL {step}: {err}");
+    anyhow::Error::new(err).context(format!("selftest failed at {step}"))
+}
+
+/// `{"status":"ok"}`, plus the account's `fulldomain` when there is one.
+fn ok_json(fulldomain: Option<&str>) -> Value {
+    let mut out = json!({ "status": "ok" });
+    if let Some(fulldomain) = fulldomain {
+        out["fulldomain"] = json!(fulldomain);
+    }
+    out
+}
+
+/// What `config` prints with `--json`. The password is left out.
+fn config_json(api_base: Option<&str>, source: &str, creds: &Result<Credentials, Error>) -> Value {
+    let credentials = match creds {
+        Ok(creds) => json!({
+            "source": source,
+            "username": creds.username,
+            "subdomain": creds.subdomain,
+            "fulldomain": creds.fulldomain,
+            "allowfrom": creds.allowfrom,
+        }),
+        Err(e) => json!({ "source": source, "error": e.to_string() }),
+    };
+    json!({ "status": "ok", "api_base": api_base, "credentials": credentials })
+}
+
+/// `{"status":"error","error":"..."}`, plus the HTTP status and body when
+/// the server rejected the request.
+fn error_json(err: &anyhow::Error) -> Value {
+    let mut out = json!({ "status": "error", "error": format!("{err:#}") });
+    match err.downcast_ref::<Error>() {
+        Some(Error::UnexpectedStatus { status, body, .. }) => {
+            out["http_status"] = json!(status.as_u16());
+            out["body"] = json!(body);
+        }
+        Some(Error::Unauthorized { status, body, .. } | Error::Forbidden { status, body, .. }) => {
+            out["http_status"] = json!(status.as_u16());
+            out["body"] = json!(body);
+        }
+        Some(Error::RateLimited {
+            retry_after, body, ..
+        }) => {
+            out["http_status"] = json!(429);
+            out["body"] = json!(body);
+            if let Some(delay) = retry_after {
+                out["retry_after"] = json!(delay.as_secs());
+            }
+        }
+        _ => {}
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acme_dns_client::{BoxFuture, DnsResolver};
+    use httpmock::prelude::*;
+    use reqwest::StatusCode;
+    use reqwest::header::HeaderMap;
+
+    /// Serves `txt` at every name.
+    #[derive(Debug)]
+    struct EveryName(String);
+
+    impl DnsResolver for EveryName {
+        fn lookup_txt<'a>(&'a self, _name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Ok(vec![self.0.clone()]) })
+        }
+    }
+
+    fn mock_server(update_status: u16) -> MockServer {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "allowfrom": [],
+                "fulldomain": "8e57.auth.example.org",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(update_status);
+        });
+        server
+    }
+
+    fn propagation() -> PropagationOptions {
+        PropagationOptions::new()
+            .timeout(Duration::from_millis(100))
+            .interval(Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn selftest_runs_every_step() {
+        let server = mock_server(200);
+        let txt = dns01_txt_value("acme-dns-cli selftest 8e57");
+        let client = AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .with_resolver(EveryName(txt.clone()));
+        let mut confirmed = false;
+        let mut out = Vec::new();
+
+        selftest(
+            &client,
+            "www.example.com",
+            propagation(),
+            || {
+                confirmed = true;
+                Ok(())
+            },
+            &mut out,
+        )
+        .await
+        .unwrap();
+
+        assert!(confirmed);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                "ok   health",
+                "ok   register: 8e57.auth.example.org",
+                "     create: _acme-challenge.www.example.com. CNAME 8e57.auth.example.org.",
+                &format!("ok   update: {txt}"),
+                "ok   resolve: 8e57.auth.example.org",
+                "ok   resolve via CNAME: _acme-challenge.www.example.com",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn selftest_stops_at_the_failed_step() {
+        let server = mock_server(400);
+        let client = AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .with_resolver(EveryName("stale".into()));
+        let mut out = Vec::new();
+
+        let err = selftest(
+            &client,
+            "www.example.com",
+            propagation(),
+            || Ok(()),
+            &mut out,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "selftest failed at update");
+        let out = String::from_utf8(out).unwrap();
+        let last = out.lines().last().unwrap();
+        assert!(last.starts_with("FAIL update: "), "{out}");
+        assert!(!out.contains("resolve"), "{out}");
+    }
+
+    #[test]
+    fn ok_json_shape() {
+        assert_eq!(
+            ok_json(Some("8e57.auth.example.org")),
+            json!({ "status": "ok", "fulldomain": "8e57.auth.example.org" })
+        );
+        assert_eq!(ok_json(None), json!({ "status": "ok" }));
+    }
+
+    #[test]
+    fn error_json_includes_http_status_and_body() {
+        let err = anyhow::Error::from(Error::UnexpectedStatus {
+            status: StatusCode::BAD_REQUEST,
+            headers: HeaderMap::new(),
+            body: "bad_txt".into(),
+        });
+
+        assert_eq!(
+            error_json(&err),
+            json!({
+                "status": "error",
+                "error": "server returned unexpected status 400 Bad Request: bad_txt",
+                "http_status": 400,
+                "body": "bad_txt",
+            })
+        );
+    }
+
+    #[test]
+    fn error_json_keeps_forbidden_status() {
+        let err = anyhow::Error::from(Error::Forbidden {
+            status: StatusCode::FORBIDDEN,
+            reason: "update not allowed from IP".into(),
+            body: r#"{"error": "update not allowed from IP"}"#.into(),
+            request_id: None,
+        });
+
+        assert_eq!(
+            error_json(&err),
+            json!({
+                "status": "error",
+                "error": "update rejected by allowfrom: update not allowed from IP",
+                "http_status": 403,
+                "body": r#"{"error": "update not allowed from IP"}"#,
+            })
+        );
+    }
+
+    #[test]
+    fn error_json_includes_rate_limit_body() {
+        let err = anyhow::Error::from(Error::RateLimited {
+            retry_after: Some(Duration::from_secs(30)),
+            request_id: None,
+            headers: Box::default(),
+            body: r#"{"error":"rate_limited","retry_after":30}"#.into(),
+        });
+
+        assert_eq!(
+            error_json(&err),
+            json!({
+                "status": "error",
+                "error": "rate limited by server, retry after 30s",
+                "http_status": 429,
+                "body": r#"{"error":"rate_limited","retry_after":30}"#,
+                "retry_after": 30,
+            })
+        );
+    }
+
+    #[test]
+    fn error_json_without_response() {
+        let err = anyhow::Error::from(Error::MissingEnv("ACME_DNS_USERNAME"));
+
+        assert_eq!(
+            error_json(&err),
+            json!({
+                "status": "error",
+                "error": "missing required environment variable ACME_DNS_USERNAME",
+            })
+        );
+    }
+}
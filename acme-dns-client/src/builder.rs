@@ -0,0 +1,690 @@
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::Client as HttpClient;
+use reqwest::header::{CONTENT_TYPE, HOST, HeaderMap, HeaderName, HeaderValue};
+use url::Url;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::dns::nameserver_resolver;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::har::HarRecorder;
+use crate::retry::NewBackoff;
+use crate::{
+    AcmeDnsClient, Backoff, Credentials, CredentialsCallback, DEFAULT_MAX_RESPONSE_SIZE, Error,
+    ExponentialBackoff, MetricsObserver, RegisterBody, RetryPolicy,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{DnsResolver, RedirectPolicy, SystemResolver};
+
+/// Builder for [`AcmeDnsClient`], for when [`AcmeDnsClient::new`]'s defaults
+/// are not enough.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use acme_dns_client::AcmeDnsClient;
+/// let client = AcmeDnsClient::builder("https://auth.example.org/")
+///     .timeout(Duration::from_secs(10))
+///     .build()?;
+/// # Ok::<(), acme_dns_client::Error>(())
+/// ```
+pub struct AcmeDnsClientBuilder {
+    base_url: String,
+    timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_max_idle_per_host: Option<usize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_idle_timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    resolve_overrides: Vec<(String, SocketAddr)>,
+    host_header: Option<String>,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "http2"))]
+    http2_prior_knowledge: bool,
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "rustls-tls", feature = "native-tls")
+    ))]
+    accept_invalid_certs: bool,
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "rustls-tls", feature = "native-tls")
+    ))]
+    root_ca_pems: Vec<Vec<u8>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    resolver: Option<Arc<dyn DnsResolver>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    redirect_policy: RedirectPolicy,
+    backoff: Option<Arc<dyn NewBackoff>>,
+    dry_run: bool,
+    metrics: Option<Arc<dyn MetricsObserver>>,
+    on_credentials_changed: Option<CredentialsCallback>,
+    register_body: RegisterBody,
+    max_response_size: usize,
+    require_uuid_subdomain: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    record_har: Option<PathBuf>,
+    #[cfg(feature = "reqwest-middleware")]
+    middleware: Option<reqwest_middleware::ClientWithMiddleware>,
+    client_ip_header: Option<(String, String)>,
+    register_auth: Option<(String, String)>,
+    json_content_type: Option<String>,
+    expected_host: Option<String>,
+    bulk_update: bool,
+    lockout_check: Option<String>,
+    register_idempotency_key: bool,
+    default_headers: Vec<(String, String)>,
+    http: Option<HttpClient>,
+}
+
+/// Shows only the names of [`default_header`](AcmeDnsClientBuilder::default_header)s,
+/// whose values are often proxy secrets, and of the
+/// [`register_auth`](AcmeDnsClientBuilder::register_auth) header.
+impl fmt::Debug for AcmeDnsClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header_names: Vec<&str> = self
+            .default_headers
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect();
+        let mut d = f.debug_struct("AcmeDnsClientBuilder");
+        d.field("base_url", &self.base_url);
+        d.field("timeout", &self.timeout);
+        #[cfg(not(target_arch = "wasm32"))]
+        d.field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("resolve_overrides", &self.resolve_overrides);
+        d.field("host_header", &self.host_header);
+        #[cfg(all(not(target_arch = "wasm32"), feature = "http2"))]
+        d.field("http2_prior_knowledge", &self.http2_prior_knowledge);
+        #[cfg(all(
+            not(target_arch = "wasm32"),
+            any(feature = "rustls-tls", feature = "native-tls")
+        ))]
+        d.field("accept_invalid_certs", &self.accept_invalid_certs)
+            .field("root_ca_pems", &self.root_ca_pems.len());
+        #[cfg(not(target_arch = "wasm32"))]
+        d.field("resolver", &self.resolver)
+            .field("redirect_policy", &self.redirect_policy);
+        d.field("backoff", &self.backoff)
+            .field("dry_run", &self.dry_run)
+            .field("metrics", &self.metrics)
+            .field("on_credentials_changed", &self.on_credentials_changed)
+            .field("register_body", &self.register_body)
+            .field("max_response_size", &self.max_response_size)
+            .field("require_uuid_subdomain", &self.require_uuid_subdomain);
+        #[cfg(not(target_arch = "wasm32"))]
+        d.field("record_har", &self.record_har);
+        #[cfg(feature = "reqwest-middleware")]
+        d.field("middleware", &self.middleware);
+        d.field("client_ip_header", &self.client_ip_header)
+            .field(
+                "register_auth",
+                &self.register_auth.as_ref().map(|(name, _)| (name, "***")),
+            )
+            .field("json_content_type", &self.json_content_type)
+            .field("expected_host", &self.expected_host)
+            .field("bulk_update", &self.bulk_update)
+            .field("lockout_check", &self.lockout_check)
+            .field("register_idempotency_key", &self.register_idempotency_key)
+            .field("default_headers", &header_names)
+            .field("http", &self.http)
+            .finish()
+    }
+}
+
+impl AcmeDnsClientBuilder {
+    pub(crate) fn new(base_url: impl AsRef<str>) -> Self {
+        Self {
+            base_url: base_url.as_ref().to_string(),
+            timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool_max_idle_per_host: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool_idle_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            resolve_overrides: Vec::new(),
+            host_header: None,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "http2"))]
+            http2_prior_knowledge: false,
+            #[cfg(all(
+                not(target_arch = "wasm32"),
+                any(feature = "rustls-tls", feature = "native-tls")
+            ))]
+            accept_invalid_certs: false,
+            #[cfg(all(
+                not(target_arch = "wasm32"),
+                any(feature = "rustls-tls", feature = "native-tls")
+            ))]
+            root_ca_pems: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            resolver: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            redirect_policy: RedirectPolicy::default(),
+            backoff: None,
+            dry_run: false,
+            metrics: None,
+            on_credentials_changed: None,
+            register_body: RegisterBody::default(),
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            require_uuid_subdomain: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            record_har: None,
+            #[cfg(feature = "reqwest-middleware")]
+            middleware: None,
+            client_ip_header: None,
+            register_auth: None,
+            json_content_type: None,
+            expected_host: None,
+            bulk_update: false,
+            lockout_check: None,
+            register_idempotency_key: false,
+            default_headers: Vec::new(),
+            http: None,
+        }
+    }
+
+    /// Overall timeout for each HTTP request. No timeout by default.
+    ///
+    /// Ignored on wasm32, where reqwest has no client-wide timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host for reuse. reqwest
+    /// keeps any number by default.
+    ///
+    /// Lowering this bounds the sockets left open after a burst of requests,
+    /// e.g. renewing many domains at once.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Close idle pooled connections after `timeout` (reqwest defaults to
+    /// 90 seconds).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Connect to `addr` whenever a request is for `host`, instead of
+    /// looking `host` up in DNS, e.g. to reach acme-dns at an internal IP
+    /// while keeping its public hostname in the base URL. Only the IP of
+    /// `addr` is used; the port comes from the URL as usual. Call
+    /// repeatedly for several hosts.
+    ///
+    /// TLS still checks the certificate against `host` (and sends it as
+    /// SNI), which is what keeps a wrong or hijacked address from getting
+    /// the API key. Together with `danger_accept_invalid_certs` that
+    /// protection is gone. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resolve(mut self, host: impl Into<String>, addr: SocketAddr) -> Self {
+        self.resolve_overrides.push((host.into(), addr));
+        self
+    }
+
+    /// Send `Host: value` on every request instead of the base URL's host,
+    /// e.g. for a virtual-hosting proxy reached by IP.
+    ///
+    /// This changes only the header: TLS still uses the URL's host for SNI
+    /// and certificate checks, so with an `https://<ip>/` base URL the
+    /// server's certificate must cover the IP. To present the public name
+    /// in TLS as well, put it in the base URL and use
+    /// [`resolve`](Self::resolve) instead. An invalid value is reported by
+    /// [`build`](Self::build) as [`Error::InvalidHeader`].
+    pub fn host_header(mut self, value: impl Into<String>) -> Self {
+        self.host_header = Some(value.into());
+        self
+    }
+
+    /// Speak HTTP/2 from the first byte instead of negotiating it.
+    ///
+    /// Needed for servers or proxies that only accept cleartext HTTP/2
+    /// (h2c) on an `http://` URL; over HTTPS, HTTP/2 is negotiated anyway.
+    /// Servers that also speak HTTP/1.1 will reject these connections.
+    /// Requires the `http2` feature.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "http2"))]
+    pub fn http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// **Disable TLS certificate verification.** For test labs with
+    /// self-signed certificates only; never use this in production.
+    ///
+    /// With verification off, anyone who can intercept the connection can
+    /// impersonate the server and read your acme-dns password, then publish
+    /// challenge values for your domains. A warning is logged via `tracing`
+    /// whenever a client is built with this enabled.
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "rustls-tls", feature = "native-tls")
+    ))]
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Trust the CA certificate(s) in `pem` in addition to the system
+    /// roots, e.g. for an acme-dns behind a private CA. Prefer this over
+    /// [`danger_accept_invalid_certs`](Self::danger_accept_invalid_certs).
+    ///
+    /// `pem` is the text of a `.pem`/`.crt` file: one or more
+    /// `-----BEGIN CERTIFICATE-----` blocks. Call repeatedly for several
+    /// files. Input without a certificate is reported by
+    /// [`build`](Self::build) as [`Error::InvalidCertificate`].
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "rustls-tls", feature = "native-tls")
+    ))]
+    pub fn root_ca_pem(mut self, pem: impl AsRef<[u8]>) -> Self {
+        self.root_ca_pems.push(pem.as_ref().to_vec());
+        self
+    }
+
+    /// Use an existing `reqwest::Client` instead of building one.
+    ///
+    /// Clients built this way share its connection pool, which saves
+    /// connections when many `AcmeDnsClient`s talk to the same host. The
+    /// HTTP-level options on this builder ([`timeout`](Self::timeout),
+    /// [`default_header`](Self::default_header),
+    /// [`redirect_policy`](Self::redirect_policy),
+    /// [`resolve`](Self::resolve), [`host_header`](Self::host_header), the
+    /// `pool_*` and TLS options) are ignored; configure those on the `reqwest::Client`
+    /// instead.
+    pub fn http_client(mut self, http: HttpClient) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Send requests through a `reqwest-middleware` stack, e.g. for
+    /// distributed tracing.
+    ///
+    /// `client` executes every request, so as with
+    /// [`http_client`](Self::http_client) the HTTP-level options on this
+    /// builder are ignored; configure them on the `reqwest::Client` inside
+    /// `client`. The builder's [`retry`](Self::retry) runs outside the
+    /// stack; errors from the middleware itself become
+    /// [`Error::Middleware`] and are not retried.
+    #[cfg(feature = "reqwest-middleware")]
+    pub fn middleware_client(mut self, client: reqwest_middleware::ClientWithMiddleware) -> Self {
+        self.middleware = Some(client);
+        self
+    }
+
+    /// Send an extra header on every request, e.g. the
+    /// `CF-Access-Client-Id`/`CF-Access-Client-Secret` pair Cloudflare
+    /// Access needs. Call repeatedly for several headers; these are sent
+    /// alongside the `X-Api-*` headers on `/update`.
+    ///
+    /// Invalid names or values are reported by [`build`](Self::build) as
+    /// [`Error::InvalidHeader`].
+    pub fn default_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.default_headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send `name: value` on `/update` requests, e.g.
+    /// `X-Forwarded-For: 203.0.113.7`, to pose as another client address.
+    ///
+    /// For testing `allowfrom` rules against an acme-dns that trusts a
+    /// client-IP header from its proxy (`header_name` in its config). Unlike
+    /// [`default_header`](Self::default_header), this is only sent on
+    /// `/update` and does apply to an [`http_client`](Self::http_client).
+    /// Invalid names or values are reported by [`build`](Self::build) as
+    /// [`Error::InvalidHeader`].
+    pub fn client_ip_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client_ip_header = Some((name.into(), value.into()));
+        self
+    }
+
+    /// Send `value` as the `Content-Type` of JSON request bodies (on
+    /// `/register`, `/update` and `/allowfrom`) instead of
+    /// `application/json`, e.g. `application/json; charset=utf-8` for a
+    /// WAF that insists on it. An invalid value is reported by
+    /// [`build`](Self::build) as [`Error::InvalidHeader`].
+    pub fn json_content_type(mut self, value: impl Into<String>) -> Self {
+        self.json_content_type = Some(value.into());
+        self
+    }
+
+    /// Refuse to send anything unless the base URL's host is `host`
+    /// (ignoring case and a trailing dot), failing each call with
+    /// [`Error::HostMismatch`] instead, e.g. to keep production
+    /// credentials from going to a staging server by mistake.
+    pub fn expected_host(mut self, host: impl Into<String>) -> Self {
+        self.expected_host = Some(host.into());
+        self
+    }
+
+    /// Send a fresh random `Idempotency-Key` (a UUID) with each
+    /// [`register`](AcmeDnsClient::register) call, the same on all of its
+    /// [`retry`](Self::retry) attempts, so a proxy that honors the header
+    /// can answer a retry with the account it already created instead of
+    /// making a second one. acme-dns itself ignores it. Off by default.
+    pub fn register_idempotency_key(mut self, enable: bool) -> Self {
+        self.register_idempotency_key = enable;
+        self
+    }
+
+    /// Retry transient failures (timeouts, connection errors, 429 and 5xx)
+    /// according to `policy`. Requests are not retried by default.
+    ///
+    /// Note that this includes `/register`: if a response is lost after the
+    /// server created the account, the retry creates a second one, unless
+    /// a proxy deduplicates them by
+    /// [`register_idempotency_key`](Self::register_idempotency_key).
+    pub fn retry(self, policy: RetryPolicy) -> Self {
+        self.backoff(ExponentialBackoff::new(policy))
+    }
+
+    /// Retry transient failures with a custom [`Backoff`] strategy, such as
+    /// [`DecorrelatedJitter`](crate::DecorrelatedJitter). Replaces any
+    /// earlier [`retry`](Self::retry) policy.
+    pub fn backoff(mut self, backoff: impl Backoff + Clone + 'static) -> Self {
+        self.backoff = Some(Arc::new(backoff));
+        self
+    }
+
+    /// Log requests via `tracing` instead of sending them.
+    ///
+    /// Each request's method, URL, headers (API key redacted) and body are
+    /// logged at info level. `update_txt` and `health` then report success;
+    /// `register` returns [`Error::DryRun`] since there are no credentials
+    /// to return. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Report every HTTP round-trip to `observer`.
+    pub fn metrics(mut self, observer: Arc<dyn MetricsObserver>) -> Self {
+        self.metrics = Some(observer);
+        self
+    }
+
+    /// Call `callback` whenever the client obtains new or changed
+    /// credentials: from [`register`](AcmeDnsClient::register) and
+    /// [`update_allowfrom`](AcmeDnsClient::update_allowfrom).
+    ///
+    /// Use it to persist credentials automatically, e.g. with
+    /// [`Credentials::save_to_path`].
+    pub fn on_credentials_changed(
+        mut self,
+        callback: impl Fn(&Credentials) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_credentials_changed = Some(CredentialsCallback(Arc::new(callback)));
+        self
+    }
+
+    /// What `register` sends as the body when there is no `allowfrom`.
+    /// Defaults to [`RegisterBody::EmptyObject`], i.e. `{}`.
+    pub fn register_body(mut self, body: RegisterBody) -> Self {
+        self.register_body = body;
+        self
+    }
+
+    /// Check that `creds.subdomain` is a UUID before each update, failing
+    /// with [`Error::InvalidSubdomain`] without sending anything; see
+    /// [`validate_subdomain`](crate::validate_subdomain). Off by default,
+    /// since some forks use other subdomain formats.
+    pub fn require_uuid_subdomain(mut self, require: bool) -> Self {
+        self.require_uuid_subdomain = require;
+        self
+    }
+
+    /// Before registering with an `allowfrom`, ask `service` for this
+    /// host's public IP and fail with [`Error::WouldLockOut`] if none of
+    /// the networks covers it, instead of creating an account this host
+    /// could never update. Off by default.
+    ///
+    /// `service` must answer a `GET` with the caller's address as plain
+    /// text, as e.g. `https://api.ipify.org` and `https://ifconfig.me/ip`
+    /// do. It should see the same outbound address acme-dns will, so
+    /// behind split routing or a proxy the check can mislead. An invalid
+    /// URL is reported by [`build`](Self::build).
+    ///
+    /// The check uses its own HTTP client with only the
+    /// [`timeout`](Self::timeout) and TLS settings, so neither
+    /// [`default_header`](Self::default_header)s nor the
+    /// [`host_header`](Self::host_header) are sent to `service`.
+    pub fn check_allowfrom_lockout(mut self, service: impl Into<String>) -> Self {
+        self.lockout_check = Some(service.into());
+        self
+    }
+
+    /// Send [`update_txt_bulk`](AcmeDnsClient::update_txt_bulk) as a single
+    /// `/update` with a JSON array body. Only for acme-dns forks that
+    /// accept it; off by default, which sends one request per value.
+    pub fn bulk_update(mut self, bulk: bool) -> Self {
+        self.bulk_update = bulk;
+        self
+    }
+
+    /// Record every HTTP exchange to a HAR (HTTP Archive) file at `path`,
+    /// e.g. to attach to a support ticket or open in a browser's devtools.
+    ///
+    /// The file is rewritten after each response and created readable only
+    /// by the owner. The `X-Api-Key` header, other sensitive headers and
+    /// the `password` in `/register` responses are replaced with `***`.
+    /// Requests that fail before a response arrives are not recorded. Not
+    /// available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record_har(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_har = Some(path.into());
+        self
+    }
+
+    /// Which redirects to follow. Defaults to
+    /// [`RedirectPolicy::SameHostOnly`], so a moved server cannot lead the
+    /// client to send the API key to another host.
+    ///
+    /// Not available on wasm32, where the browser handles redirects.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Give up reading a response body larger than `bytes` with
+    /// [`Error::ResponseTooLarge`], so a misbehaving server cannot make the
+    /// client buffer an arbitrary amount. Defaults to 16 KiB.
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = bytes;
+        self
+    }
+
+    /// Resolver for DNS lookups such as [`AcmeDnsClient::current_txt`].
+    ///
+    /// Accepts a pre-built hickory `TokioResolver` or any other
+    /// [`DnsResolver`]. Defaults to [`SystemResolver`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resolver(mut self, resolver: impl DnsResolver + 'static) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Send DNS lookups straight to `addr` (e.g. `1.1.1.1:53`) instead of the
+    /// system resolver, for split-horizon networks.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn nameserver(self, addr: SocketAddr) -> Self {
+        self.resolver(nameserver_resolver(addr))
+    }
+
+    /// Send `name: value` on `/register` only, e.g. an admin API key for an
+    /// acme-dns fork that closes registration to the public.
+    ///
+    /// Not sent on any other request, and redacted in
+    /// [`dry_run`](Self::dry_run) logs. Invalid names or values are reported
+    /// by [`build`](Self::build) as [`Error::InvalidHeader`].
+    pub fn register_auth(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.register_auth = Some((name.into(), value.into()));
+        self
+    }
+
+    /// Build the client.
+    pub fn build(self) -> Result<AcmeDnsClient, Error> {
+        let base_url = parse_base_url(&self.base_url)?;
+        let http = match &self.http {
+            Some(http) => http.clone(),
+            None => self.build_http()?,
+        };
+
+        let client_ip_header = match &self.client_ip_header {
+            Some((name, value)) => Some(parse_header(name, value)?),
+            None => None,
+        };
+        let register_auth = match &self.register_auth {
+            Some((name, value)) => {
+                let (name, mut value) = parse_header(name, value)?;
+                value.set_sensitive(true);
+                Some((name, value))
+            }
+            None => None,
+        };
+
+        let lockout_check = match self.lockout_check.as_deref() {
+            Some(service) => Some((Url::parse(service)?, self.http_builder()?.build()?)),
+            None => None,
+        };
+        let json_content_type = match &self.json_content_type {
+            Some(value) => Some(
+                HeaderValue::try_from(value.as_str())
+                    .map_err(|_| Error::InvalidHeader(CONTENT_TYPE.to_string()))?,
+            ),
+            None => None,
+        };
+
+        Ok(AcmeDnsClient {
+            base_url,
+            http,
+            client_ip_header,
+            register_auth,
+            json_content_type,
+            expected_host: self.expected_host,
+            bulk_update: self.bulk_update,
+            lockout_check,
+            register_idempotency_key: self.register_idempotency_key,
+            #[cfg(not(target_arch = "wasm32"))]
+            resolver: self
+                .resolver
+                .unwrap_or_else(|| Arc::new(SystemResolver::new())),
+            backoff: self.backoff,
+            dry_run: self.dry_run,
+            metrics: self.metrics,
+            on_credentials_changed: self.on_credentials_changed,
+            register_body: self.register_body,
+            max_response_size: self.max_response_size,
+            require_uuid_subdomain: self.require_uuid_subdomain,
+            #[cfg(not(target_arch = "wasm32"))]
+            har: self.record_har.map(|path| Arc::new(HarRecorder::new(path))),
+            #[cfg(feature = "reqwest-middleware")]
+            middleware: self.middleware,
+        })
+    }
+
+    fn build_http(&self) -> Result<HttpClient, Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.default_headers {
+            let (name, value) = parse_header(name, value)?;
+            headers.append(name, value);
+        }
+        if let Some(host) = &self.host_header {
+            let (name, value) = parse_header(HOST.as_str(), host)?;
+            headers.insert(name, value);
+        }
+
+        #[allow(unused_mut)]
+        let mut http = self.http_builder()?.default_headers(headers);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(max) = self.pool_max_idle_per_host {
+            http = http.pool_max_idle_per_host(max);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.pool_idle_timeout {
+            http = http.pool_idle_timeout(timeout);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            http = http.redirect(self.redirect_policy.to_reqwest());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        for (host, addr) in &self.resolve_overrides {
+            http = http.resolve(host, *addr);
+        }
+        #[cfg(all(not(target_arch = "wasm32"), feature = "http2"))]
+        if self.http2_prior_knowledge {
+            http = http.http2_prior_knowledge();
+        }
+        Ok(http.build()?)
+    }
+
+    /// A `reqwest` builder with just the timeout and TLS settings, shared
+    /// by the API client and the lockout check's.
+    fn http_builder(&self) -> Result<reqwest::ClientBuilder, Error> {
+        #[allow(unused_mut)]
+        let mut http = HttpClient::builder();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        #[cfg(all(
+            not(target_arch = "wasm32"),
+            any(feature = "rustls-tls", feature = "native-tls")
+        ))]
+        for pem in &self.root_ca_pems {
+            let certs = reqwest::Certificate::from_pem_bundle(pem)
+                .map_err(|e| Error::InvalidCertificate(e.to_string()))?;
+            if certs.is_empty() {
+                return Err(Error::InvalidCertificate(
+                    "no PEM certificate found".to_string(),
+                ));
+            }
+            for cert in certs {
+                http = http.add_root_certificate(cert);
+            }
+        }
+        #[cfg(all(
+            not(target_arch = "wasm32"),
+            any(feature = "rustls-tls", feature = "native-tls")
+        ))]
+        if self.accept_invalid_certs {
+            tracing::warn!(
+                base_url = %self.base_url,
+                "TLS certificate verification is disabled; do not use this in production"
+            );
+            http = http.danger_accept_invalid_certs(true);
+        }
+        Ok(http)
+    }
+}
+
+fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue), Error> {
+    let invalid = || Error::InvalidHeader(name.to_string());
+    Ok((
+        HeaderName::try_from(name).map_err(|_| invalid())?,
+        HeaderValue::try_from(value).map_err(|_| invalid())?,
+    ))
+}
+
+/// Parse an API base URL, making sure its path ends in `/`.
+pub(crate) fn parse_base_url(base_url: &str) -> Result<Url, Error> {
+    let mut url = Url::parse(base_url)?;
+    // `Url::join` replaces the last path segment unless the base ends in
+    // `/`, which would turn `https://host/api` + `register` into
+    // `https://host/register`.
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
@@ -0,0 +1,41 @@
+//! Cancelling waits and retry loops from the outside.
+
+use std::future::Future;
+
+use futures_util::future::{self, Either};
+
+use crate::Error;
+
+/// Run `operation` until it finishes or `cancel` resolves, whichever comes
+/// first, failing with [`Error::Cancelled`] in the latter case.
+///
+/// Works with any client method, including the long-running ones such as
+/// [`wait_for_propagation`](crate::AcmeDnsClient::wait_for_propagation),
+/// [`wait_healthy`](crate::AcmeDnsClient::wait_healthy) and requests that
+/// are being retried. `cancel` can be any future, e.g.
+/// `tokio_util::sync::CancellationToken::cancelled()` or a shutdown signal:
+///
+/// ```no_run
+/// # async fn run(
+/// #     client: acme_dns_client::AcmeDnsClient,
+/// #     creds: acme_dns_client::Credentials,
+/// #     shutdown: impl std::future::Future<Output = ()>,
+/// # ) -> Result<(), acme_dns_client::Error> {
+/// acme_dns_client::cancellable(shutdown, client.update_txt(&creds, "token")).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The operation is dropped on cancellation, so a request already sent may
+/// or may not have reached the server.
+pub async fn cancellable<T>(
+    cancel: impl Future<Output = ()>,
+    operation: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let operation = std::pin::pin!(operation);
+    let cancel = std::pin::pin!(cancel);
+    match future::select(operation, cancel).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(Error::Cancelled),
+    }
+}
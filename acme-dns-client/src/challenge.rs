@@ -0,0 +1,145 @@
+//! Helpers for the DNS-01 challenge itself (RFC 8555 §8.4).
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use sha2::{Digest, Sha256};
+
+/// Compute the TXT value a DNS-01 challenge expects for `key_authorization`.
+///
+/// The key authorization is `<token>.<account key thumbprint>`; what goes in
+/// DNS is `base64url(sha256(key_authorization))` without padding. Passing the
+/// raw key authorization to [`update_txt`](crate::AcmeDnsClient::update_txt)
+/// is a common mistake and validation will fail.
+pub fn dns01_txt_value(key_authorization: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(key_authorization.as_bytes()))
+}
+
+/// DNS-01 helpers on a key authorization, whichever ACME crate it came from.
+///
+/// ```
+/// use acme_dns_client::KeyAuthorizationExt;
+///
+/// let key_auth = String::from_token_and_thumbprint("token", "thumbprint");
+/// assert_eq!(key_auth, "token.thumbprint");
+/// let txt = key_auth.to_dns01_txt();
+/// # assert_eq!(txt, acme_dns_client::dns01_txt_value("token.thumbprint"));
+/// ```
+pub trait KeyAuthorizationExt {
+    /// The TXT value for this key authorization; see [`dns01_txt_value`].
+    fn to_dns01_txt(&self) -> String;
+
+    /// Build the key authorization `<token>.<thumbprint>` from the
+    /// challenge token and the base64url JWK thumbprint (RFC 7638) of the
+    /// account key.
+    fn from_token_and_thumbprint(token: &str, thumbprint: &str) -> Self
+    where
+        Self: Sized + From<String>,
+    {
+        format!("{token}.{thumbprint}").into()
+    }
+}
+
+impl KeyAuthorizationExt for str {
+    fn to_dns01_txt(&self) -> String {
+        dns01_txt_value(self)
+    }
+}
+
+impl KeyAuthorizationExt for String {
+    fn to_dns01_txt(&self) -> String {
+        dns01_txt_value(self)
+    }
+}
+
+/// Check whether `observed_txt` is the DNS-01 value for
+/// `key_authorization`, e.g. to debug a failed validation offline.
+///
+/// Surrounding whitespace and double quotes (as printed by `dig`) are
+/// ignored.
+pub fn verify_txt_matches(key_authorization: &str, observed_txt: &str) -> bool {
+    observed_txt.trim().trim_matches('"') == dns01_txt_value(key_authorization)
+}
+
+/// The name a DNS-01 challenge for `domain` is looked up at, e.g.
+/// `_acme-challenge.example.com`.
+///
+/// A wildcard domain shares its base domain's record, so `*.example.com`
+/// also gives `_acme-challenge.example.com`. A trailing dot is dropped.
+pub fn challenge_fqdn(domain: &str) -> String {
+    let domain = domain.trim_end_matches('.');
+    let domain = domain.strip_prefix("*.").unwrap_or(domain);
+    format!("_acme-challenge.{domain}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns01_txt_value_is_rfc8555_digest() {
+        // Token from RFC 8555 §8.4, thumbprint from RFC 7638 §3.1.
+        let key_auth = "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA\
+                        .NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs";
+
+        assert_eq!(
+            dns01_txt_value(key_auth),
+            "ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll8"
+        );
+    }
+
+    #[test]
+    fn key_authorization_ext_matches_rfc8555() {
+        let key_auth = String::from_token_and_thumbprint(
+            "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA",
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs",
+        );
+
+        assert_eq!(
+            key_auth,
+            "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA\
+             .NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert_eq!(
+            key_auth.to_dns01_txt(),
+            "ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll8"
+        );
+        assert_eq!(key_auth.as_str().to_dns01_txt(), key_auth.to_dns01_txt());
+    }
+
+    #[test]
+    fn challenge_fqdn_handles_wildcards_and_trailing_dots() {
+        assert_eq!(challenge_fqdn("example.com"), "_acme-challenge.example.com");
+        assert_eq!(
+            challenge_fqdn("*.example.com"),
+            "_acme-challenge.example.com"
+        );
+        assert_eq!(
+            challenge_fqdn("www.example.com."),
+            "_acme-challenge.www.example.com"
+        );
+        assert_eq!(
+            challenge_fqdn("*.example.com."),
+            "_acme-challenge.example.com"
+        );
+    }
+
+    #[test]
+    fn verify_txt_matches_recomputes_the_digest() {
+        let key_auth = "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA\
+                        .NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs";
+
+        assert!(verify_txt_matches(
+            key_auth,
+            "ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll8"
+        ));
+        assert!(verify_txt_matches(
+            key_auth,
+            "\"ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll8\"\n"
+        ));
+        assert!(!verify_txt_matches(key_auth, key_auth));
+        assert!(!verify_txt_matches(
+            key_auth,
+            "ZTRx1Ckl1-tM05o5zaizTTA0yUy5AGereMgSNWC6Ll9"
+        ));
+    }
+}
@@ -0,0 +1,535 @@
+//! DNS lookups against the records acme-dns is serving.
+//!
+//! The client does not talk DNS itself; it goes through a [`DnsResolver`].
+//! By default that is [`SystemResolver`] (hickory, configured from the host's
+//! resolv.conf). Split-horizon networks can point lookups at a specific
+//! server with [`nameserver_resolver`], and tests can plug in their own.
+
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use hickory_resolver::lookup::TxtLookup;
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::xfer::Protocol;
+
+use crate::Error;
+
+/// Boxed future returned by [`DnsResolver`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// TXT values with their TTL, as returned by
+/// [`DnsResolver::lookup_txt_with_ttl`].
+pub type TxtWithTtl = (Vec<String>, Option<Duration>);
+
+/// Which IP family to reach nameservers over; see
+/// [`DnsResolver::nameserver_over`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IpFamily {
+    /// Whichever address the nameserver's host name resolves to first.
+    #[default]
+    Any,
+    /// Only the nameserver's A records, and query it over IPv4.
+    V4,
+    /// Only the nameserver's AAAA records, and query it over IPv6.
+    V6,
+}
+
+impl IpFamily {
+    fn matches(self, ip: IpAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IpFamily::Any => "any",
+            IpFamily::V4 => "IPv4",
+            IpFamily::V6 => "IPv6",
+        })
+    }
+}
+
+/// Something that can resolve TXT records.
+///
+/// Implementations should return [`Error::NxDomain`] when the name does not
+/// exist, and an empty vec when it exists but has no TXT records.
+pub trait DnsResolver: fmt::Debug + Send + Sync {
+    /// Resolve the TXT records at `name`, one string per record.
+    fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>>;
+
+    /// Like [`lookup_txt`](Self::lookup_txt), also returning the records'
+    /// TTL (the lowest, if they differ), which
+    /// [`PropagationOptions::ttl_interval`](crate::PropagationOptions::ttl_interval)
+    /// times its polls by. The default reports no TTL.
+    fn lookup_txt_with_ttl<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        Box::pin(async move { Ok((self.lookup_txt(name).await?, None)) })
+    }
+
+    /// Like [`lookup_txt_with_ttl`](Self::lookup_txt_with_ttl), but fail
+    /// with [`Error::DnssecValidation`] unless the answer is
+    /// DNSSEC-validated; used by
+    /// [`PropagationOptions::require_dnssec`](crate::PropagationOptions::require_dnssec).
+    ///
+    /// The default reports that this resolver cannot validate. The hickory
+    /// resolvers in this crate can with the `dnssec` feature.
+    fn lookup_txt_validated<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        let _ = name;
+        Box::pin(async move {
+            Err(Error::DnssecValidation(format!(
+                "{self:?} cannot validate DNSSEC"
+            )))
+        })
+    }
+
+    /// Resolve the NS records of `zone`, as nameserver host names.
+    ///
+    /// Used when checking propagation on every authoritative nameserver.
+    /// The default reports that this resolver cannot do it.
+    fn lookup_ns<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        let _ = zone;
+        Box::pin(async move { Err(Error::Dns(format!("{self:?} cannot look up NS records"))) })
+    }
+
+    /// A resolver that sends its queries straight to the nameserver `host`.
+    ///
+    /// The default reports that this resolver cannot do it.
+    fn nameserver<'a>(
+        &'a self,
+        host: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        let _ = host;
+        Box::pin(async move {
+            Err(Error::Dns(format!(
+                "{self:?} cannot query nameservers directly"
+            )))
+        })
+    }
+
+    /// Like [`nameserver`](Self::nameserver), but reach `host` only over
+    /// `family`: look up just its A (or AAAA) records and send the queries
+    /// to that address.
+    ///
+    /// The default handles [`IpFamily::Any`] with `nameserver` and reports
+    /// that this resolver cannot do the others.
+    fn nameserver_over<'a>(
+        &'a self,
+        host: &'a str,
+        family: IpFamily,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        if family == IpFamily::Any {
+            return self.nameserver(host);
+        }
+        Box::pin(async move {
+            Err(Error::Dns(format!(
+                "{self:?} cannot query nameservers over {family} only"
+            )))
+        })
+    }
+}
+
+/// Resolver built from the host's system DNS configuration.
+///
+/// The underlying hickory resolver is created on first use, so constructing
+/// a client never fails because resolv.conf is unreadable.
+#[derive(Debug, Default)]
+pub struct SystemResolver {
+    inner: OnceLock<TokioResolver>,
+    #[cfg(feature = "dnssec")]
+    validating: OnceLock<TokioResolver>,
+}
+
+impl SystemResolver {
+    /// Create a resolver that reads the system configuration on first lookup.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn resolver(&self) -> Result<&TokioResolver, Error> {
+        if let Some(resolver) = self.inner.get() {
+            return Ok(resolver);
+        }
+        let resolver = TokioResolver::builder_tokio()
+            .map_err(|e| Error::Dns(e.to_string()))?
+            .build();
+        Ok(self.inner.get_or_init(|| resolver))
+    }
+
+    /// Like [`resolver`](Self::resolver), with DNSSEC validation on.
+    #[cfg(feature = "dnssec")]
+    fn validating(&self) -> Result<&TokioResolver, Error> {
+        if let Some(resolver) = self.validating.get() {
+            return Ok(resolver);
+        }
+        let mut builder = TokioResolver::builder_tokio().map_err(|e| Error::Dns(e.to_string()))?;
+        builder.options_mut().validate = true;
+        let resolver = builder.build();
+        Ok(self.validating.get_or_init(|| resolver))
+    }
+}
+
+/// Build a resolver that sends every query to `addr` (e.g. `1.1.1.1:53`).
+///
+/// Queries go over UDP with TCP fallback, and answers are not cached so
+/// repeated lookups always reflect what the server is serving right now.
+pub fn nameserver_resolver(addr: SocketAddr) -> TokioResolver {
+    nameserver_resolver_with(addr, false)
+}
+
+fn nameserver_resolver_with(addr: SocketAddr, validate: bool) -> TokioResolver {
+    let config = ResolverConfig::from_parts(
+        None,
+        vec![],
+        vec![
+            NameServerConfig::new(addr, Protocol::Udp),
+            NameServerConfig::new(addr, Protocol::Tcp),
+        ],
+    );
+    let mut opts = ResolverOpts::default();
+    opts.cache_size = 0;
+    opts.validate = validate;
+
+    TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+        .with_options(opts)
+        .build()
+}
+
+impl DnsResolver for SystemResolver {
+    fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        Box::pin(async move { Ok(lookup_txt_with(self.resolver()?, name).await?.0) })
+    }
+
+    fn lookup_txt_with_ttl<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        Box::pin(async move { lookup_txt_with(self.resolver()?, name).await })
+    }
+
+    #[cfg(feature = "dnssec")]
+    fn lookup_txt_validated<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        Box::pin(async move { lookup_txt_validated_with(self.validating()?, name).await })
+    }
+
+    fn lookup_ns<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        Box::pin(async move { lookup_ns_with(self.resolver()?, zone).await })
+    }
+
+    fn nameserver<'a>(
+        &'a self,
+        host: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        self.nameserver_over(host, IpFamily::Any)
+    }
+
+    fn nameserver_over<'a>(
+        &'a self,
+        host: &'a str,
+        family: IpFamily,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        Box::pin(async move { nameserver_with(self.resolver()?, host, family).await })
+    }
+}
+
+impl DnsResolver for TokioResolver {
+    fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        Box::pin(async move { Ok(lookup_txt_with(self, name).await?.0) })
+    }
+
+    fn lookup_txt_with_ttl<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        Box::pin(lookup_txt_with(self, name))
+    }
+
+    /// Only validates if the resolver was built with
+    /// `ResolverOpts::validate` set; otherwise every answer fails.
+    #[cfg(feature = "dnssec")]
+    fn lookup_txt_validated<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        Box::pin(lookup_txt_validated_with(self, name))
+    }
+
+    fn lookup_ns<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        Box::pin(lookup_ns_with(self, zone))
+    }
+
+    fn nameserver<'a>(
+        &'a self,
+        host: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        Box::pin(nameserver_with(self, host, IpFamily::Any))
+    }
+
+    fn nameserver_over<'a>(
+        &'a self,
+        host: &'a str,
+        family: IpFamily,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        Box::pin(nameserver_with(self, host, family))
+    }
+}
+
+async fn lookup_txt_with(resolver: &TokioResolver, name: &str) -> Result<TxtWithTtl, Error> {
+    match resolver.txt_lookup(name).await {
+        Ok(lookup) => Ok(txt_answer(&lookup)),
+        Err(e) if e.is_nx_domain() => Err(Error::NxDomain(name.to_string())),
+        Err(e) if e.is_no_records_found() => Ok((Vec::new(), None)),
+        Err(e) => Err(Error::Dns(e.to_string())),
+    }
+}
+
+/// Like [`lookup_txt_with`], requiring every TXT record to be proven secure.
+#[cfg(feature = "dnssec")]
+async fn lookup_txt_validated_with(
+    resolver: &TokioResolver,
+    name: &str,
+) -> Result<TxtWithTtl, Error> {
+    use hickory_resolver::proto::rr::RecordType;
+
+    match resolver.txt_lookup(name).await {
+        Ok(lookup) => {
+            let insecure = lookup
+                .as_lookup()
+                .dnssec_record_iter()
+                .map(|r| r.into_parts())
+                .filter(|(_, record)| record.record_type() == RecordType::TXT)
+                .map(|(proof, _)| proof)
+                .find(|proof| !proof.is_secure());
+            match insecure {
+                Some(proof) => Err(Error::DnssecValidation(format!(
+                    "TXT answer for {name} is {proof}"
+                ))),
+                None => Ok(txt_answer(&lookup)),
+            }
+        }
+        Err(e) if e.is_nx_domain() => Err(Error::NxDomain(name.to_string())),
+        Err(e) if e.is_no_records_found() => Ok((Vec::new(), None)),
+        Err(e) => Err(Error::Dns(e.to_string())),
+    }
+}
+
+/// The values of `lookup`, one string per record, and their lowest TTL.
+fn txt_answer(lookup: &TxtLookup) -> TxtWithTtl {
+    let values = lookup
+        .iter()
+        .map(|txt| {
+            txt.txt_data()
+                .iter()
+                .map(|chunk| String::from_utf8_lossy(chunk))
+                .collect()
+        })
+        .collect();
+    let ttl = lookup.as_lookup().records().iter().map(|r| r.ttl()).min();
+    (values, ttl.map(|t| Duration::from_secs(t.into())))
+}
+
+async fn lookup_ns_with(resolver: &TokioResolver, zone: &str) -> Result<Vec<String>, Error> {
+    match resolver.ns_lookup(zone).await {
+        Ok(lookup) => Ok(lookup
+            .iter()
+            .map(|ns| ns.0.to_ascii().trim_end_matches('.').to_string())
+            .collect()),
+        Err(e) if e.is_nx_domain() => Err(Error::NxDomain(zone.to_string())),
+        Err(e) if e.is_no_records_found() => Ok(Vec::new()),
+        Err(e) => Err(Error::Dns(e.to_string())),
+    }
+}
+
+/// Resolve `host` with `resolver`, to an address in `family`, and build a
+/// resolver that queries it on port 53. An IP address is used as is.
+async fn nameserver_with(
+    resolver: &TokioResolver,
+    host: &str,
+    family: IpFamily,
+) -> Result<Arc<dyn DnsResolver>, Error> {
+    let dns_error = |e: hickory_resolver::ResolveError| Error::Dns(e.to_string());
+    let ip = match host.parse::<IpAddr>() {
+        Ok(ip) if family.matches(ip) => Some(ip),
+        Ok(_) => {
+            return Err(Error::Dns(format!(
+                "nameserver {host} is not an {family} address"
+            )));
+        }
+        Err(_) => match family {
+            IpFamily::Any => resolver
+                .lookup_ip(host)
+                .await
+                .map_err(dns_error)?
+                .iter()
+                .next(),
+            IpFamily::V4 => {
+                let lookup = resolver.ipv4_lookup(host).await.map_err(dns_error)?;
+                lookup.iter().next().map(|a| IpAddr::V4(a.0))
+            }
+            IpFamily::V6 => {
+                let lookup = resolver.ipv6_lookup(host).await.map_err(dns_error)?;
+                lookup.iter().next().map(|aaaa| IpAddr::V6(aaaa.0))
+            }
+        },
+    };
+    let ip = ip.ok_or_else(|| Error::Dns(format!("no {family} address for nameserver {host}")))?;
+    Ok(Arc::new(Nameserver::new(SocketAddr::new(ip, 53))))
+}
+
+/// What [`DnsResolver::nameserver`] returns for the hickory resolvers: a
+/// [`nameserver_resolver`], plus a validating twin (built on first use) for
+/// [`DnsResolver::lookup_txt_validated`].
+#[derive(Debug)]
+struct Nameserver {
+    plain: TokioResolver,
+    #[cfg(feature = "dnssec")]
+    addr: SocketAddr,
+    #[cfg(feature = "dnssec")]
+    validating: OnceLock<TokioResolver>,
+}
+
+impl Nameserver {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            plain: nameserver_resolver(addr),
+            #[cfg(feature = "dnssec")]
+            addr,
+            #[cfg(feature = "dnssec")]
+            validating: OnceLock::new(),
+        }
+    }
+}
+
+impl DnsResolver for Nameserver {
+    fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        self.plain.lookup_txt(name)
+    }
+
+    fn lookup_txt_with_ttl<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        self.plain.lookup_txt_with_ttl(name)
+    }
+
+    #[cfg(feature = "dnssec")]
+    fn lookup_txt_validated<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+        let resolver = self
+            .validating
+            .get_or_init(|| nameserver_resolver_with(self.addr, true));
+        Box::pin(lookup_txt_validated_with(resolver, name))
+    }
+
+    fn lookup_ns<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+        self.plain.lookup_ns(zone)
+    }
+
+    fn nameserver<'a>(
+        &'a self,
+        host: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+        self.plain.nameserver(host)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use hickory_resolver::proto::rr::rdata::TXT;
+    use hickory_resolver::proto::rr::{RData, Record};
+    use std::collections::HashMap;
+    use tokio::net::UdpSocket;
+
+    /// Serve `answers` (name -> TXT values) over UDP on an ephemeral port.
+    /// Unknown names get NXDOMAIN.
+    pub(crate) async fn spawn_txt_server(answers: HashMap<String, Vec<String>>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let request = Message::from_vec(&buf[..len]).unwrap();
+                let query = request.queries()[0].clone();
+
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(request.recursion_desired())
+                    .add_query(query.clone());
+
+                let name = query.name().to_ascii();
+                match answers.get(name.trim_end_matches('.')) {
+                    Some(values) => {
+                        for value in values {
+                            response.add_answer(Record::from_rdata(
+                                query.name().clone(),
+                                60,
+                                RData::TXT(TXT::new(vec![value.clone()])),
+                            ));
+                        }
+                    }
+                    None => {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+
+                socket
+                    .send_to(&response.to_vec().unwrap(), peer)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn nameserver_resolver_queries_the_given_server() {
+        let addr = spawn_txt_server(HashMap::from([(
+            "8e57.auth.example.org".to_string(),
+            vec!["token123".to_string()],
+        )]))
+        .await;
+
+        let resolver = nameserver_resolver(addr);
+        let txt = resolver.lookup_txt("8e57.auth.example.org").await.unwrap();
+        assert_eq!(txt, vec!["token123"]);
+        let (_, ttl) = resolver
+            .lookup_txt_with_ttl("8e57.auth.example.org")
+            .await
+            .unwrap();
+        assert_eq!(ttl, Some(Duration::from_secs(60)));
+
+        let err = resolver
+            .lookup_txt("missing.auth.example.org")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NxDomain(_)), "got {err:?}");
+    }
+}
@@ -0,0 +1,114 @@
+//! Passphrase-protected credentials files.
+//!
+//! Layout: `MAGIC`, a 16-byte Argon2id salt, a 24-byte XChaCha20-Poly1305
+//! nonce, then the sealed JSON written by
+//! [`save_to_path`](crate::Credentials::save_to_path).
+
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use crate::{Credentials, Error};
+
+const MAGIC: &[u8] = b"acme-dns-client:enc:v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+impl Credentials {
+    /// Like [`save_to_path`](Self::save_to_path), but encrypt the file
+    /// under `passphrase`. Load it again with
+    /// [`load_encrypted`](Self::load_encrypted).
+    pub fn save_encrypted(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<(), Error> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let sealed = cipher
+            .encrypt(&nonce, serde_json::to_vec(self)?.as_slice())
+            .map_err(|_| Error::Decrypt)?;
+
+        let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + sealed.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        crate::write_private(path.as_ref(), &out)
+    }
+
+    /// Load credentials written by [`save_encrypted`](Self::save_encrypted).
+    ///
+    /// A wrong passphrase, or a file that was tampered with, gives
+    /// [`Error::Decrypt`].
+    pub fn load_encrypted(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, Error> {
+        let data = std::fs::read(path)?;
+        let rest = data.strip_prefix(MAGIC).ok_or(Error::Decrypt)?;
+        if rest.len() < SALT_LEN + NONCE_LEN {
+            return Err(Error::Decrypt);
+        }
+        let (salt, rest) = rest.split_at(SALT_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+        let json = cipher
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| Error::Decrypt)?;
+        Self::from_json_slice(&json)
+    }
+}
+
+/// Argon2id with the crate's default (OWASP-recommended) parameters.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::Decrypt)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds() -> Credentials {
+        Credentials::builder()
+            .username("user")
+            .password("secret")
+            .subdomain("sub")
+            .fulldomain("sub.auth.example.org")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.enc");
+
+        creds().save_encrypted(&path, "hunter2").unwrap();
+
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"secret"));
+        let loaded = Credentials::load_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(loaded.username, "user");
+        assert_eq!(loaded.password, "secret");
+        assert_eq!(loaded.fulldomain, "sub.auth.example.org");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_decrypt_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.enc");
+        creds().save_encrypted(&path, "hunter2").unwrap();
+
+        let err = Credentials::load_encrypted(&path, "hunter3").unwrap_err();
+        assert!(matches!(err, Error::Decrypt), "{err:?}");
+
+        creds().save_to_path(&path).unwrap();
+        let err = Credentials::load_encrypted(&path, "hunter2").unwrap_err();
+        assert!(matches!(err, Error::Decrypt), "{err:?}");
+    }
+}
@@ -1,4 +1,8 @@
-use reqwest::StatusCode;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use http::{HeaderMap, StatusCode};
 use thiserror::Error;
 use url::ParseError as UrlParseError;
 
@@ -8,15 +12,493 @@ pub enum Error {
     #[error("URL parse error: {0}")]
     Url(#[from] UrlParseError),
 
+    #[cfg(feature = "client")]
     #[error("HTTP error: {0}")]
     Http(#[from] reqwest::Error),
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// `headers` holds the full response headers (e.g. `Retry-After`) for
+    /// programmatic inspection; they are left out of the message.
     #[error("server returned unexpected status {status}: {body}")]
-    UnexpectedStatus { status: StatusCode, body: String },
+    UnexpectedStatus {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: String,
+    },
+
+    /// The server answered `401` or `403` (`status`). Retrying will not
+    /// help. `request_id` is the response's `X-Request-Id`, if any.
+    #[error("credentials rejected by server: {body}")]
+    Unauthorized {
+        status: StatusCode,
+        body: String,
+        request_id: Option<String>,
+    },
+
+    /// The server answered `401` or `403` because the request came from an
+    /// address outside the account's `allowfrom`, as opposed to
+    /// [`Error::Unauthorized`] for bad credentials. `status` is the `401`
+    /// or `403`, `reason` the server's message and `request_id` the
+    /// response's `X-Request-Id`, if any.
+    ///
+    /// Upstream acme-dns answers both with `{"error": "forbidden"}`, which
+    /// stays [`Error::Unauthorized`]; this needs a server or proxy whose
+    /// `{"error": ...}` message says `allowfrom` or `not allowed from`.
+    #[error("update rejected by allowfrom: {reason}")]
+    Forbidden {
+        status: StatusCode,
+        reason: String,
+        body: String,
+        request_id: Option<String>,
+    },
+
+    /// The server answered `429 Too Many Requests`. `retry_after` comes
+    /// from the `Retry-After` header or, failing that, a JSON body like
+    /// `{"error": "rate_limited", "retry_after": 30}` (in seconds); the
+    /// built-in [`retry`](crate::AcmeDnsClientBuilder::retry) waits that
+    /// long before trying again. `request_id` is the response's
+    /// `X-Request-Id`, if any; `headers` (boxed to keep [`Error`] small)
+    /// and `body` are the full response, left out of the message.
+    #[error("rate limited by server{}", .retry_after.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited {
+        retry_after: Option<Duration>,
+        request_id: Option<String>,
+        headers: Box<HeaderMap>,
+        body: String,
+    },
 
     #[error("missing required environment variable {0}")]
     MissingEnv(&'static str),
+
+    /// Several required environment variables are missing, or any number
+    /// under a custom prefix (see
+    /// [`Credentials::from_env_prefixed`](crate::Credentials::from_env_prefixed));
+    /// see [`Credentials::check_env`](crate::Credentials::check_env).
+    #[error("missing required environment variables {}", .0.join(", "))]
+    MissingEnvVars(Vec<String>),
+
+    /// A required field was not set on a
+    /// [`CredentialsBuilder`](crate::CredentialsBuilder).
+    #[error("missing required credentials field {0}")]
+    MissingField(&'static str),
+
+    /// A required file is missing from systemd's `$CREDENTIALS_DIRECTORY`.
+    #[error("missing required systemd credential {0}")]
+    MissingCredential(&'static str),
+
+    #[error("invalid CIDR in allowfrom: {0}")]
+    InvalidCidr(String),
+
+    /// See [`validate_subdomain`](crate::validate_subdomain).
+    #[error("subdomain is not a UUID: {0}")]
+    InvalidSubdomain(String),
+
+    #[error("invalid HTTP header: {0}")]
+    InvalidHeader(String),
+
+    /// The base URL's host is not the one set with
+    /// [`expected_host`](crate::AcmeDnsClientBuilder::expected_host); no
+    /// request was sent.
+    #[error("base URL host {actual} does not match expected host {expected}")]
+    HostMismatch { expected: String, actual: String },
+
+    #[error("invalid root certificate: {0}")]
+    InvalidCertificate(String),
+
+    #[error("ACME client error: {0}")]
+    Acme(String),
+
+    /// [`update_txt_compare_and_set`](crate::AcmeDnsClient::update_txt_compare_and_set):
+    /// DNS did not serve the expected value (`None`: expected no value), so
+    /// nothing was sent. `found` is what it served.
+    #[error("TXT precondition failed: expected {}, found [{}]", .expected.as_deref().unwrap_or("no value"), .found.join(", "))]
+    Precondition {
+        expected: Option<String>,
+        found: Vec<String>,
+    },
+
+    /// [`register`](crate::AcmeDnsClient::register) was asked for an
+    /// `allowfrom` that does not cover this host's public address `ip`, so
+    /// every later update from here would be rejected; see
+    /// [`check_allowfrom_lockout`](crate::AcmeDnsClientBuilder::check_allowfrom_lockout).
+    /// Nothing was registered.
+    #[error("allowfrom [{}] does not include this host's address {ip}", .allowfrom.join(", "))]
+    WouldLockOut { ip: IpAddr, allowfrom: Vec<String> },
+
+    /// The service set with
+    /// [`check_allowfrom_lockout`](crate::AcmeDnsClientBuilder::check_allowfrom_lockout)
+    /// did not answer with an IP address.
+    #[error("public IP check failed: {0}")]
+    IpCheck(String),
+
+    #[error("dry run: request was not sent")]
+    DryRun,
+
+    /// A `*_with_deadline` operation did not finish in time.
+    #[error("deadline exceeded")]
+    Timeout,
+
+    #[error("DNS lookup failed: {0}")]
+    Dns(String),
+
+    #[error("DNS name does not exist: {0}")]
+    NxDomain(String),
+
+    /// A TXT answer was not DNSSEC-validated although
+    /// [`PropagationOptions::require_dnssec`](crate::PropagationOptions::require_dnssec)
+    /// asked for it.
+    #[error("DNSSEC validation failed: {0}")]
+    DnssecValidation(String),
+
+    /// The expected TXT value was not visible everywhere before the wait
+    /// timed out. `answers` holds what each server returned on the last
+    /// poll.
+    #[error("TXT record for {name} did not propagate in time: {}", join_answers(.answers))]
+    PropagationTimeout {
+        name: String,
+        answers: Vec<ServerAnswer>,
+    },
+
+    /// `/health` did not succeed within `waited`; `last` is the error from
+    /// the final poll.
+    #[error("server not healthy after {waited:?}: {last}")]
+    HealthTimeout { waited: Duration, last: Box<Error> },
+
+    /// [`update_txt_transactional`](crate::AcmeDnsClient::update_txt_transactional):
+    /// the new value did not propagate (`cause`), so the values served
+    /// before (or a placeholder, if there were none) were set again.
+    /// `restored` is what the record serves now.
+    #[error("{cause}; rolled back to [{}]", .restored.join(", "))]
+    RolledBack {
+        cause: Box<Error>,
+        restored: Vec<String>,
+    },
+
+    /// Like [`Error::RolledBack`], but setting the previous values again
+    /// failed too, so the new value may still be in place.
+    #[error("{cause}; rollback failed: {rollback}")]
+    RollbackFailed {
+        cause: Box<Error>,
+        rollback: Box<Error>,
+    },
+
+    /// A middleware set with
+    /// [`AcmeDnsClientBuilder::middleware_client`](crate::AcmeDnsClientBuilder::middleware_client)
+    /// failed the request.
+    #[cfg(feature = "reqwest-middleware")]
+    #[error("middleware error: {0}")]
+    Middleware(anyhow::Error),
+
+    /// [`Credentials::load_encrypted`](crate::Credentials::load_encrypted):
+    /// wrong passphrase, or the file is corrupt or not an encrypted
+    /// credentials file.
+    #[cfg(feature = "encryption")]
+    #[error("could not decrypt credentials (wrong passphrase or corrupt file)")]
+    Decrypt,
+
+    /// A response body was larger than
+    /// [`max_response_size`](crate::AcmeDnsClientBuilder::max_response_size)
+    /// allows.
+    #[cfg(feature = "client")]
+    #[error("response body exceeds {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+
+    /// The operation was cancelled; see [`cancellable`](crate::cancellable).
+    #[error("operation cancelled")]
+    Cancelled,
+}
+
+/// What one DNS server returned during a propagation check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAnswer {
+    /// The nameserver queried, or `"resolver"` for the client's resolver.
+    pub server: String,
+    /// The TXT values it served, or why the lookup failed.
+    pub txt: Result<Vec<String>, String>,
+}
+
+impl fmt::Display for ServerAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.txt {
+            Ok(values) => write!(f, "{} served [{}]", self.server, values.join(", ")),
+            Err(e) => write!(f, "{} failed: {e}", self.server),
+        }
+    }
+}
+
+fn join_answers(answers: &[ServerAnswer]) -> String {
+    answers
+        .iter()
+        .map(ServerAnswer::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Coarse classification of an [`Error`](enum@Error), for retry decisions.
+///
+/// `reqwest` failures all arrive as [`Error::Http`]; [`Error::kind`] looks
+/// inside them to tell timeouts and connection failures apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The request, connection or a propagation wait timed out.
+    Timeout,
+    /// Could not connect (DNS resolution of the API host, refused, TLS handshake).
+    Connect,
+    /// The request could not be built or sent.
+    Request,
+    /// Any other HTTP transport error, e.g. while reading the body.
+    Http,
+    /// The server answered with a status we did not expect.
+    Status,
+    /// The server rejected the credentials.
+    Unauthorized,
+    /// The response body could not be decoded.
+    Decode,
+    /// Invalid URL or missing configuration.
+    Config,
+    /// A DNS lookup of the challenge records failed.
+    Dns,
+    /// The client is in dry-run mode and sent nothing.
+    DryRun,
+    /// The caller cancelled the operation.
+    Cancelled,
+}
+
+impl Error {
+    /// Classify this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            #[cfg(feature = "client")]
+            Error::Http(e) if e.is_timeout() => ErrorKind::Timeout,
+            #[cfg(feature = "client")]
+            Error::Http(e) if is_connect(e) => ErrorKind::Connect,
+            #[cfg(feature = "client")]
+            Error::Http(e) if e.is_request() || e.is_builder() => ErrorKind::Request,
+            #[cfg(feature = "client")]
+            Error::Http(e) if e.is_decode() => ErrorKind::Decode,
+            #[cfg(feature = "client")]
+            Error::Http(_) => ErrorKind::Http,
+            #[cfg(feature = "client")]
+            Error::ResponseTooLarge { .. } => ErrorKind::Http,
+            Error::Acme(_) => ErrorKind::Request,
+            Error::IpCheck(_) => ErrorKind::Http,
+            #[cfg(feature = "reqwest-middleware")]
+            Error::Middleware(_) => ErrorKind::Request,
+            Error::Json(_) => ErrorKind::Decode,
+            Error::UnexpectedStatus { .. }
+            | Error::RateLimited { .. }
+            | Error::Precondition { .. } => ErrorKind::Status,
+            Error::Unauthorized { .. } | Error::Forbidden { .. } => ErrorKind::Unauthorized,
+            Error::Url(_)
+            | Error::MissingEnv(_)
+            | Error::MissingEnvVars(_)
+            | Error::MissingCredential(_)
+            | Error::MissingField(_)
+            | Error::Io(_)
+            | Error::InvalidCidr(_)
+            | Error::InvalidSubdomain(_)
+            | Error::InvalidHeader(_)
+            | Error::HostMismatch { .. }
+            | Error::WouldLockOut { .. }
+            | Error::InvalidCertificate(_) => ErrorKind::Config,
+            #[cfg(feature = "encryption")]
+            Error::Decrypt => ErrorKind::Config,
+            Error::Dns(_) | Error::NxDomain(_) | Error::DnssecValidation(_) => ErrorKind::Dns,
+            Error::Timeout | Error::PropagationTimeout { .. } | Error::HealthTimeout { .. } => {
+                ErrorKind::Timeout
+            }
+            Error::RolledBack { cause, .. } | Error::RollbackFailed { cause, .. } => cause.kind(),
+            Error::DryRun => ErrorKind::DryRun,
+            Error::Cancelled => ErrorKind::Cancelled,
+        }
+    }
+
+    /// The `X-Request-Id` the server (or a proxy in front of it) sent with
+    /// an error response ([`Error::UnexpectedStatus`],
+    /// [`Error::Unauthorized`], [`Error::Forbidden`] or
+    /// [`Error::RateLimited`]), for correlating with its logs.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Error::UnexpectedStatus { headers, .. } => request_id(headers),
+            Error::Unauthorized { request_id, .. }
+            | Error::Forbidden { request_id, .. }
+            | Error::RateLimited { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether trying the same call again might succeed.
+    ///
+    /// True for timeouts, connection failures, `5xx` and `429 Too Many
+    /// Requests` (exactly what the built-in [`retry`] retries); false for
+    /// everything else, including other `4xx`, rejected credentials and bad
+    /// input.
+    ///
+    /// [`retry`]: crate::AcmeDnsClientBuilder::retry
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(feature = "client")]
+            Error::Http(e) => e.is_timeout() || is_connect(e),
+            Error::UnexpectedStatus { status, .. } => is_retryable_status(*status),
+            Error::RateLimited { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// How long an [`Error::RateLimited`] asks to wait before retrying.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "reqwest-middleware")]
+impl From<reqwest_middleware::Error> for Error {
+    fn from(err: reqwest_middleware::Error) -> Self {
+        match err {
+            reqwest_middleware::Error::Reqwest(e) => Error::Http(e),
+            reqwest_middleware::Error::Middleware(e) => Error::Middleware(e),
+        }
+    }
+}
+
+/// A serializable summary of an [`Error`](enum@Error), e.g. for returning it from an
+/// HTTP service as JSON.
+///
+/// Inner errors (such as `reqwest::Error`) are not serializable, so only
+/// their message survives.
+///
+/// ```
+/// # use acme_dns_client::{Error, ErrorResponse};
+/// let response = ErrorResponse::from(&Error::Timeout);
+/// assert_eq!(response.kind, "timeout");
+/// assert_eq!(response.status, None);
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ErrorResponse {
+    /// The [`ErrorKind`] in snake case, e.g. `"unauthorized"`.
+    pub kind: String,
+    /// The HTTP status the server answered with, if it got that far.
+    pub status: Option<u16>,
+    /// The error message.
+    pub message: String,
+}
+
+#[cfg(feature = "serde")]
+impl From<&Error> for ErrorResponse {
+    fn from(err: &Error) -> Self {
+        let status = match err {
+            Error::UnexpectedStatus { status, .. }
+            | Error::Unauthorized { status, .. }
+            | Error::Forbidden { status, .. } => Some(status.as_u16()),
+            Error::RateLimited { .. } => Some(429),
+            #[cfg(feature = "client")]
+            Error::Http(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        };
+        let kind = match err.kind() {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Request => "request",
+            ErrorKind::Http => "http",
+            ErrorKind::Status => "status",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Decode => "decode",
+            ErrorKind::Config => "config",
+            ErrorKind::Dns => "dns",
+            ErrorKind::DryRun => "dry_run",
+            ErrorKind::Cancelled => "cancelled",
+        };
+        Self {
+            kind: kind.to_string(),
+            status,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// The error for a `401`/`403` `status` with `body`: [`Error::Forbidden`]
+/// if the server's `{"error": ...}` message contains `allowfrom` or
+/// `not allowed from` (ignoring case), [`Error::Unauthorized`] otherwise.
+#[cfg(feature = "client")]
+pub(crate) fn rejection(status: StatusCode, body: String, request_id: Option<String>) -> Error {
+    #[derive(serde::Deserialize)]
+    struct ErrorBody {
+        error: String,
+    }
+
+    let reason = serde_json::from_str::<ErrorBody>(&body)
+        .ok()
+        .map(|b| b.error);
+    let blames_address = |reason: &str| {
+        let reason = reason.to_ascii_lowercase();
+        reason.contains("allowfrom") || reason.contains("not allowed from")
+    };
+    match reason {
+        Some(reason) if blames_address(&reason) => Error::Forbidden {
+            status,
+            reason,
+            body,
+            request_id,
+        },
+        _ => Error::Unauthorized {
+            status,
+            body,
+            request_id,
+        },
+    }
+}
+
+/// [`Error::RateLimited`] for a `429` response, waiting as long as its
+/// `Retry-After` header says or else the `retry_after` seconds in a JSON
+/// `body`.
+#[cfg(feature = "client")]
+pub(crate) fn rate_limited(headers: HeaderMap, body: String, request_id: Option<String>) -> Error {
+    #[derive(serde::Deserialize)]
+    struct RateLimitBody {
+        retry_after: u64,
+    }
+
+    let retry_after = crate::retry::retry_after(&headers).or_else(|| {
+        serde_json::from_str::<RateLimitBody>(&body)
+            .ok()
+            .map(|b| Duration::from_secs(b.retry_after))
+    });
+    Error::RateLimited {
+        retry_after,
+        request_id,
+        headers: Box::new(headers),
+        body,
+    }
+}
+
+/// The `X-Request-Id` header, if present and valid UTF-8.
+pub(crate) fn request_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get("x-request-id")?.to_str().ok()
+}
+
+/// Whether a response with `status` is worth retrying.
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether `e` failed while connecting. reqwest on wasm32 cannot tell.
+#[cfg(feature = "client")]
+pub(crate) fn is_connect(e: &reqwest::Error) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    return e.is_connect();
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = e;
+        false
+    }
 }
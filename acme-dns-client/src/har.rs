@@ -0,0 +1,224 @@
+//! Recording HTTP exchanges as a HAR (HTTP Archive 1.2) file; see
+//! [`AcmeDnsClientBuilder::record_har`](crate::AcmeDnsClientBuilder::record_har).
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use reqwest::{Request, Response, Version};
+use serde_json::{Value, json};
+use web_time::Instant;
+
+use crate::Error;
+
+/// Collects entries and rewrites the file after each one, so it is valid
+/// HAR even if the process dies mid-run.
+#[derive(Debug)]
+pub(crate) struct HarRecorder {
+    path: PathBuf,
+    entries: Mutex<Vec<Value>>,
+}
+
+/// The request half of an entry, captured before sending.
+pub(crate) struct PendingEntry {
+    started: SystemTime,
+    timer: Instant,
+    request: Value,
+}
+
+impl HarRecorder {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Capture `request` before it is sent.
+    pub(crate) fn start(&self, request: &Request) -> PendingEntry {
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .unwrap_or_default();
+        let mut har_request = json!({
+            "method": request.method().as_str(),
+            "url": request.url().as_str(),
+            "httpVersion": http_version(request.version()),
+            "headers": headers(request.headers()),
+            "queryString": [],
+            "cookies": [],
+            "headersSize": -1,
+            "bodySize": body.len(),
+        });
+        if !body.is_empty() {
+            har_request["postData"] = json!({
+                "mimeType": mime_type(request.headers()),
+                "text": redact_body(body),
+            });
+        }
+        PendingEntry {
+            started: SystemTime::now(),
+            timer: Instant::now(),
+            request: har_request,
+        }
+    }
+
+    /// Read `resp` (up to `limit` bytes), record the exchange and hand back
+    /// an equivalent response for the caller to consume.
+    pub(crate) async fn finish(
+        &self,
+        pending: PendingEntry,
+        mut resp: Response,
+        limit: usize,
+    ) -> Result<Response, Error> {
+        let status = resp.status();
+        let version = resp.version();
+        let headers_map = resp.headers().clone();
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(Error::ResponseTooLarge { limit });
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let elapsed = pending.timer.elapsed().as_secs_f64() * 1000.0;
+
+        let entry = json!({
+            "startedDateTime": iso8601(pending.started),
+            "time": elapsed,
+            "request": pending.request,
+            "response": {
+                "status": status.as_u16(),
+                "statusText": status.canonical_reason().unwrap_or(""),
+                "httpVersion": http_version(version),
+                "headers": headers(&headers_map),
+                "cookies": [],
+                "content": {
+                    "size": body.len(),
+                    "mimeType": mime_type(&headers_map),
+                    "text": redact_body(&body),
+                },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": body.len(),
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": elapsed, "receive": 0 },
+        });
+        self.push(entry);
+
+        let mut rebuilt = http::Response::builder().status(status).version(version);
+        if let Some(h) = rebuilt.headers_mut() {
+            *h = headers_map;
+        }
+        let rebuilt = rebuilt
+            .body(body)
+            .expect("status and headers came from a valid response");
+        Ok(rebuilt.into())
+    }
+
+    fn push(&self, entry: Value) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push(entry);
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": *entries,
+            }
+        });
+        let written = serde_json::to_vec_pretty(&har)
+            .map_err(Error::from)
+            .and_then(|bytes| crate::write_private(&self.path, &bytes));
+        if let Err(e) = written {
+            tracing::warn!(path = %self.path.display(), error = %e, "could not write HAR file");
+        }
+    }
+}
+
+/// HAR headers, with the API key and other sensitive values masked.
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == "x-api-key" || value.is_sensitive() {
+                "***"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+fn mime_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+/// `body` as text, with a JSON `password` field (as in a `/register`
+/// response) masked.
+fn redact_body(body: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(mut object)) if object.contains_key("password") => {
+            object.insert("password".to_string(), json!("***"));
+            Value::Object(object).to_string()
+        }
+        _ => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn http_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// `time` as an ISO 8601 UTC timestamp with milliseconds, as HAR wants.
+fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days, from Howard Hinnant's date algorithms.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn iso8601_formats_utc() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(iso8601(t), "2024-02-29T12:34:56.789Z");
+    }
+}
@@ -0,0 +1,452 @@
+//! Health monitoring built on `GET /health`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures_util::Stream;
+use futures_util::future::{self, Either};
+use futures_util::stream;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, SERVER};
+use url::Url;
+use web_time::Instant;
+
+use crate::{AcmeDnsClient, Error, timer};
+
+/// What [`AcmeDnsClient::health_detailed`] learned about the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HealthInfo {
+    /// The server's version, from a JSON body like `{"version": "1.2.0"}`
+    /// or a `Server: acme-dns/1.2.0` header. Upstream acme-dns reports
+    /// neither, so this is often `None`.
+    pub version: Option<String>,
+}
+
+impl HealthInfo {
+    /// Whether the server reports a version of at least `version`.
+    ///
+    /// Versions compare numerically component by component (`1.10` >
+    /// `1.9`, missing components count as 0, a leading `v` is ignored), and
+    /// a pre-release like `1.2.0-rc1` sorts below `1.2.0`. Returns `false`
+    /// when the server reports no version or either version does not parse,
+    /// so "unknown" is never mistaken for "new enough".
+    pub fn is_at_least(&self, version: &str) -> bool {
+        let Some(have) = self.version.as_deref().and_then(parse_version) else {
+            return false;
+        };
+        let Some(want) = parse_version(version) else {
+            return false;
+        };
+        have >= want
+    }
+}
+
+/// Split `1.2.3-rc1` into comparable parts: numeric components padded to
+/// three, then whether it is a full release.
+fn parse_version(version: &str) -> Option<(Vec<u64>, bool)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split('+').next()?;
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (version, None),
+    };
+
+    let mut parts = core
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    while parts.len() > 3 && parts.last() == Some(&0) {
+        parts.pop();
+    }
+    parts.resize(parts.len().max(3), 0);
+    Some((parts, pre.is_none()))
+}
+
+/// What [`AcmeDnsClient::probe`] makes of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ServerKind {
+    /// `/health` answers the way acme-dns does.
+    AcmeDns,
+    /// Something else answers, such as a web server's HTML page, a login
+    /// portal or an error status.
+    Unknown,
+}
+
+/// Result of a health poll, as yielded by [`AcmeDnsClient::health_watch`].
+#[derive(Debug)]
+pub enum HealthState {
+    /// `/health` returned 200.
+    Healthy,
+    /// The check failed; carries the error from that poll.
+    Unhealthy(Error),
+}
+
+impl HealthState {
+    /// Whether this is [`HealthState::Healthy`].
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, HealthState::Healthy)
+    }
+}
+
+impl AcmeDnsClient {
+    /// Like [`health`](Self::health), but also report what the server says
+    /// about itself; see [`HealthInfo`].
+    pub async fn health_detailed(&self) -> Result<HealthInfo, Error> {
+        let resp = self.health_response().await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
+        }
+
+        let header_version = resp
+            .headers()
+            .get(SERVER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("acme-dns/"))
+            .map(str::to_string);
+        let body = self.read_body(resp).await?;
+        let body_version = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("version")?.as_str().map(str::to_string));
+
+        Ok(HealthInfo {
+            version: body_version.or(header_version),
+        })
+    }
+
+    /// Check that the server looks like acme-dns before trusting it with
+    /// credentials, e.g. to catch a mistyped URL or a captive portal.
+    ///
+    /// This is a heuristic based on `/health`: acme-dns answers 200 with an
+    /// empty body (or a small JSON object from forks that report a
+    /// version), and never HTML. A `Server: acme-dns/...` header settles
+    /// it. Anything else is [`ServerKind::Unknown`]; only failing to get a
+    /// response at all is an error.
+    pub async fn probe(&self) -> Result<ServerKind, Error> {
+        let resp = self.health_response().await?;
+
+        if resp.status() != StatusCode::OK {
+            return Ok(ServerKind::Unknown);
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_ascii_lowercase()
+        };
+        if header(SERVER).starts_with("acme-dns/") {
+            return Ok(ServerKind::AcmeDns);
+        }
+        if header(CONTENT_TYPE).contains("html") {
+            return Ok(ServerKind::Unknown);
+        }
+
+        let body = match self.read_body(resp).await {
+            Ok(body) => body,
+            Err(Error::ResponseTooLarge { .. }) => return Ok(ServerKind::Unknown),
+            Err(e) => return Err(e),
+        };
+        let body = body.trim();
+        let looks_like_acme_dns = body.is_empty()
+            || serde_json::from_str::<serde_json::Value>(body).is_ok_and(|v| v.is_object());
+        Ok(if looks_like_acme_dns {
+            ServerKind::AcmeDns
+        } else {
+            ServerKind::Unknown
+        })
+    }
+
+    /// Poll `/health` every `interval` until it succeeds, e.g. to hold off
+    /// startup until acme-dns is reachable.
+    ///
+    /// Gives up with [`Error::HealthTimeout`], carrying the last poll's
+    /// error, once another poll would end after `timeout`. Each poll is
+    /// bounded only by the client's own request
+    /// [`timeout`](crate::AcmeDnsClientBuilder::timeout), so set one to keep
+    /// a hung server from stretching the wait.
+    pub async fn wait_healthy(&self, timeout: Duration, interval: Duration) -> Result<(), Error> {
+        let started = Instant::now();
+        loop {
+            let last = match self.health().await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if started.elapsed() + interval > timeout {
+                return Err(Error::HealthTimeout {
+                    waited: started.elapsed(),
+                    last: Box::new(last),
+                });
+            }
+            timer::sleep(interval).await;
+        }
+    }
+
+    /// Poll `/health` every `interval` and yield only when the state changes.
+    ///
+    /// The first poll always yields, establishing the initial state; after
+    /// that, an item arrives only on an up->down or down->up transition.
+    /// Consecutive failures with different errors count as one down state.
+    /// The stream never ends; drop it to stop polling.
+    pub fn health_watch(&self, interval: Duration) -> impl Stream<Item = HealthState> + use<> {
+        let client = self.clone();
+
+        stream::unfold(None, move |last: Option<bool>| {
+            let client = client.clone();
+            async move {
+                if last.is_some() {
+                    timer::sleep(interval).await;
+                }
+                loop {
+                    let state = match client.health().await {
+                        Ok(()) => HealthState::Healthy,
+                        Err(e) => HealthState::Unhealthy(e),
+                    };
+                    if last != Some(state.is_healthy()) {
+                        let healthy = state.is_healthy();
+                        return Some((state, Some(healthy)));
+                    }
+                    timer::sleep(interval).await;
+                }
+            }
+        })
+    }
+}
+
+/// Check `/health` on every server in `bases` at once, e.g. to monitor a
+/// fleet of acme-dns instances.
+///
+/// Each check builds its own default client and gives up with
+/// [`Error::Timeout`] after `timeout`, so one unreachable server does not
+/// hold up the report.
+pub async fn health_all(bases: &[Url], timeout: Duration) -> HashMap<Url, Result<(), Error>> {
+    let checks = bases.iter().map(|base| async move {
+        let check = async { AcmeDnsClient::new(base.as_str())?.health().await };
+        let result = match future::select(Box::pin(check), Box::pin(timer::sleep(timeout))).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(Error::Timeout),
+        };
+        (base.clone(), result)
+    });
+    future::join_all(checks).await.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn health_all_reports_each_server() {
+        let healthy = MockServer::start_async().await;
+        healthy.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+        let broken = MockServer::start_async().await;
+        broken.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(500).body("db down");
+        });
+        let healthy = Url::parse(&healthy.base_url()).unwrap();
+        let broken = Url::parse(&broken.base_url()).unwrap();
+
+        let report = health_all(&[healthy.clone(), broken.clone()], Duration::from_secs(5)).await;
+
+        assert_eq!(report.len(), 2);
+        assert!(report[&healthy].is_ok());
+        assert!(
+            matches!(&report[&broken], Err(Error::UnexpectedStatus { status, .. }) if status.as_u16() == 500),
+            "{:?}",
+            report[&broken]
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_recognizes_acme_dns() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        assert_eq!(client.probe().await.unwrap(), ServerKind::AcmeDns);
+    }
+
+    #[tokio::test]
+    async fn probe_rejects_an_html_page() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200)
+                .header("Content-Type", "text/html; charset=utf-8")
+                .body("<!doctype html><title>Sign in</title>");
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        assert_eq!(client.probe().await.unwrap(), ServerKind::Unknown);
+    }
+
+    fn info(version: Option<&str>) -> HealthInfo {
+        HealthInfo {
+            version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn is_at_least_compares_versions() {
+        let server = info(Some("1.2.3"));
+        assert!(server.is_at_least("1.2.3"));
+        assert!(server.is_at_least("1.2"));
+        assert!(server.is_at_least("v1.1.9"));
+        assert!(!server.is_at_least("1.2.4"));
+        assert!(!server.is_at_least("1.10.0"));
+        assert!(!server.is_at_least("2"));
+
+        assert!(!info(Some("1.2.3-rc1")).is_at_least("1.2.3"));
+        assert!(info(Some("1.2.3")).is_at_least("1.2.3-rc1"));
+    }
+
+    #[test]
+    fn is_at_least_is_false_without_a_usable_version() {
+        assert!(!info(None).is_at_least("0.1"));
+        assert!(!info(Some("unknown")).is_at_least("0.1"));
+        assert!(!info(Some("1.2.3")).is_at_least("latest"));
+    }
+
+    #[tokio::test]
+    async fn health_detailed_reads_version() {
+        let server = MockServer::start_async().await;
+        let json = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(200)
+                    .header("Server", "acme-dns/0.9.0")
+                    .body(r#"{"version":"1.1.0"}"#);
+            })
+            .await;
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let health = client.health_detailed().await.unwrap();
+        assert_eq!(health.version.as_deref(), Some("1.1.0"));
+
+        json.delete_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(200).header("Server", "acme-dns/0.9.0");
+            })
+            .await;
+        let health = client.health_detailed().await.unwrap();
+        assert_eq!(health.version.as_deref(), Some("0.9.0"));
+    }
+
+    #[tokio::test]
+    async fn wait_healthy_waits_for_recovery() {
+        let server = MockServer::start_async().await;
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let down = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(503);
+            })
+            .await;
+        let recover = async {
+            while down.hits_async().await < 2 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            down.delete_async().await;
+            server
+                .mock_async(|when, then| {
+                    when.method(GET).path("/health");
+                    then.status(200);
+                })
+                .await;
+        };
+
+        let wait = client.wait_healthy(Duration::from_secs(5), Duration::from_millis(10));
+        let (result, ()) = tokio::join!(wait, recover);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_healthy_times_out_with_last_error() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(503);
+            })
+            .await;
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let err = client
+            .wait_healthy(Duration::from_millis(50), Duration::from_millis(10))
+            .await
+            .unwrap_err();
+
+        let Error::HealthTimeout { last, .. } = &err else {
+            panic!("expected HealthTimeout, got {err:?}");
+        };
+        assert!(
+            matches!(**last, Error::UnexpectedStatus { status, .. } if status == 503),
+            "got {last:?}"
+        );
+        assert_eq!(err.kind(), crate::ErrorKind::Timeout);
+    }
+
+    #[tokio::test]
+    async fn health_watch_yields_only_transitions() {
+        let server = MockServer::start_async().await;
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let up = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(200);
+            })
+            .await;
+
+        // Flip the server up -> down -> up, each state lasting several polls.
+        let flip = async {
+            while up.hits_async().await < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            up.delete_async().await;
+            let down = server
+                .mock_async(|when, then| {
+                    when.method(GET).path("/health");
+                    then.status(500);
+                })
+                .await;
+            while down.hits_async().await < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            down.delete_async().await;
+            server
+                .mock_async(|when, then| {
+                    when.method(GET).path("/health");
+                    then.status(200);
+                })
+                .await;
+        };
+
+        let watch = client
+            .health_watch(Duration::from_millis(10))
+            .take(3)
+            .collect::<Vec<_>>();
+        let (states, ()) = tokio::join!(watch, flip);
+
+        let healthy: Vec<_> = states.iter().map(HealthState::is_healthy).collect();
+        assert_eq!(healthy, vec![true, false, true]);
+        let HealthState::Unhealthy(Error::UnexpectedStatus { status, .. }) = &states[1] else {
+            panic!("expected UnexpectedStatus, got {:?}", states[1]);
+        };
+        assert_eq!(*status, 500);
+    }
+}
@@ -0,0 +1,35 @@
+//! DNS-01 glue for the [`instant-acme`](https://docs.rs/instant-acme) crate.
+//!
+//! Enabled with the `instant-acme` feature. instant-acme hands you a
+//! [`KeyAuthorization`] for each challenge; what acme-dns must serve is the
+//! base64url SHA-256 digest of it, not the key authorization itself
+//! (RFC 8555 §8.4).
+//!
+//! ```no_run
+//! # async fn example(
+//! #     client: &acme_dns_client::AcmeDnsClient,
+//! #     creds: &acme_dns_client::Credentials,
+//! #     mut challenge: instant_acme::ChallengeHandle<'_>,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use acme_dns_client::instant_acme::set_dns01_challenge;
+//!
+//! set_dns01_challenge(client, creds, &challenge.key_authorization()).await?;
+//! challenge.set_ready().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use ::instant_acme::KeyAuthorization;
+
+use crate::{AcmeDnsClient, Credentials, Error};
+
+/// Publish the DNS-01 TXT value for `key_authorization` via `/update`.
+pub async fn set_dns01_challenge(
+    client: &AcmeDnsClient,
+    creds: &Credentials,
+    key_authorization: &KeyAuthorization,
+) -> Result<(), Error> {
+    client
+        .update_txt_for_key_auth(creds, key_authorization.as_str())
+        .await
+}
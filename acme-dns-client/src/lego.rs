@@ -0,0 +1,109 @@
+//! LEGO's acme-dns storage file (`ACME_DNS_STORAGE_PATH`): a JSON object
+//! mapping each domain to the account that serves its challenges.
+//!
+//! ```json
+//! {
+//!   "example.com": {
+//!     "fulldomain": "8e57.auth.example.org",
+//!     "subdomain": "8e57",
+//!     "username": "c36f50e8-...",
+//!     "password": "htB9mR9D..."
+//!   }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value, json};
+
+use crate::{Credentials, Error};
+
+impl Credentials {
+    /// This account as one value of a LEGO storage file; see
+    /// [`lego_storage`]. LEGO has no `allowfrom`, so it is left out.
+    pub fn to_lego_entry(&self) -> Value {
+        json!({
+            "fulldomain": self.fulldomain,
+            "subdomain": self.subdomain,
+            "username": self.username,
+            "password": self.password,
+        })
+    }
+}
+
+/// Build a LEGO acme-dns storage file from `(domain, credentials)` pairs,
+/// e.g. a `HashMap<String, Credentials>`.
+///
+/// ```
+/// # use acme_dns_client::{Credentials, lego_storage};
+/// let creds = Credentials::builder()
+///     .username("user-uuid")
+///     .password("secret")
+///     .subdomain("8e57")
+///     .fulldomain("8e57.auth.example.org")
+///     .build()?;
+/// let storage = lego_storage([("example.com", &creds)]);
+/// assert_eq!(storage["example.com"]["subdomain"], "8e57");
+/// # Ok::<(), acme_dns_client::Error>(())
+/// ```
+pub fn lego_storage<'a, D: AsRef<str>>(
+    accounts: impl IntoIterator<Item = (D, &'a Credentials)>,
+) -> Value {
+    let entries: Map<String, Value> = accounts
+        .into_iter()
+        .map(|(domain, creds)| (domain.as_ref().to_string(), creds.to_lego_entry()))
+        .collect();
+    Value::Object(entries)
+}
+
+/// Read a LEGO acme-dns storage file back into domain → credentials.
+pub fn parse_lego_storage(json: &str) -> Result<BTreeMap<String, Credentials>, Error> {
+    Ok(serde_json::from_str(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds(subdomain: &str) -> Credentials {
+        Credentials::builder()
+            .username("user-uuid")
+            .password("secret")
+            .subdomain(subdomain)
+            .fulldomain(format!("{subdomain}.auth.example.org"))
+            .allowfrom(["192.168.100.0/24"])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn lego_storage_matches_lego_schema() {
+        let accounts = BTreeMap::from([
+            ("example.com".to_string(), creds("8e57")),
+            ("example.net".to_string(), creds("9f68")),
+        ]);
+
+        let storage = lego_storage(&accounts);
+        assert_eq!(
+            storage,
+            json!({
+                "example.com": {
+                    "fulldomain": "8e57.auth.example.org",
+                    "subdomain": "8e57",
+                    "username": "user-uuid",
+                    "password": "secret",
+                },
+                "example.net": {
+                    "fulldomain": "9f68.auth.example.org",
+                    "subdomain": "9f68",
+                    "username": "user-uuid",
+                    "password": "secret",
+                },
+            })
+        );
+
+        let parsed = parse_lego_storage(&storage.to_string()).unwrap();
+        assert_eq!(parsed["example.net"].fulldomain, "9f68.auth.example.org");
+        assert!(parsed["example.net"].allowfrom.is_empty());
+    }
+}
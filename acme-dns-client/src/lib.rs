@@ -9,13 +9,87 @@
 //!   3. On each DNS-01 challenge, call [`AcmeDnsClient::update_txt`]
 //!      with those credentials and the new token.
 
+#[cfg(feature = "acme2")]
+pub mod acme2;
+#[cfg(feature = "client")]
+mod batch;
+#[cfg(feature = "client")]
+mod builder;
+#[cfg(feature = "client")]
+mod cancel;
+mod challenge;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+mod dns;
+#[cfg(feature = "encryption")]
+mod encryption;
 mod error;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+mod har;
+#[cfg(feature = "client")]
+mod health;
+#[cfg(feature = "instant-acme")]
+pub mod instant_acme;
+mod lego;
+#[cfg(feature = "client")]
+mod metrics;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+mod propagation;
+#[cfg(feature = "client")]
+mod retry;
+#[cfg(feature = "client")]
+mod scheduler;
+#[cfg(feature = "client")]
+mod timer;
 
-pub use crate::error::Error;
+#[cfg(feature = "client")]
+pub use crate::batch::{BatchError, BatchOutcome};
+#[cfg(feature = "client")]
+pub use crate::builder::AcmeDnsClientBuilder;
+#[cfg(feature = "client")]
+pub use crate::cancel::cancellable;
+pub use crate::challenge::{
+    KeyAuthorizationExt, challenge_fqdn, dns01_txt_value, verify_txt_matches,
+};
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use crate::dns::{
+    BoxFuture, DnsResolver, IpFamily, SystemResolver, TxtWithTtl, nameserver_resolver,
+};
+#[cfg(feature = "serde")]
+pub use crate::error::ErrorResponse;
+pub use crate::error::{Error, ErrorKind, ServerAnswer};
+#[cfg(feature = "client")]
+pub use crate::health::{HealthInfo, HealthState, ServerKind, health_all};
+pub use crate::lego::{lego_storage, parse_lego_storage};
+#[cfg(feature = "client")]
+pub use crate::metrics::MetricsObserver;
+#[cfg(feature = "prometheus")]
+pub use crate::metrics::PrometheusObserver;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use crate::propagation::{PropagationMode, PropagationOptions, PropagationProgress};
+#[cfg(feature = "client")]
+pub use crate::retry::{Backoff, DecorrelatedJitter, ExponentialBackoff, RetryPolicy};
+#[cfg(feature = "client")]
+pub use crate::scheduler::RenewalScheduler;
 
-use reqwest::{Client as HttpClient, StatusCode};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+#[cfg(feature = "client")]
+use std::sync::Arc;
+#[cfg(feature = "client")]
+use std::time::Duration;
+
+#[cfg(feature = "client")]
+use futures_util::{StreamExt, TryStreamExt, stream};
+#[cfg(feature = "client")]
+use reqwest::header::{CONTENT_TYPE, HeaderName, HeaderValue};
+#[cfg(feature = "client")]
+use reqwest::{Client as HttpClient, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "client")]
 use url::Url;
+#[cfg(feature = "client")]
+use web_time::Instant;
 
 /// Credentials returned by `/register` and required for `/update`.
 ///
@@ -30,77 +104,429 @@ use url::Url;
 ///   "username": "c36f50e8-..."
 /// }
 /// ```
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Deserialization also accepts the `snake_case` and `camelCase`
+/// spellings some forks use (`full_domain`, `subDomain`, `allow_from`,
+/// ...); serialization always uses the upstream names.
+///
+/// `Debug` masks the password; use [`Credentials::redacted`] for a
+/// one-line form suitable for logs.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Credentials {
     pub username: String,
     pub password: String,
+    #[serde(alias = "sub_domain", alias = "subDomain")]
     pub subdomain: String,
+    #[serde(alias = "full_domain", alias = "fullDomain")]
     pub fulldomain: String,
-    #[serde(default)]
+    #[serde(default, alias = "allow_from", alias = "allowFrom")]
     pub allowfrom: Vec<String>,
 }
 
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .field("subdomain", &self.subdomain)
+            .field("fulldomain", &self.fulldomain)
+            .field("allowfrom", &self.allowfrom)
+            .finish()
+    }
+}
+
+/// Log-safe view of [`Credentials`], returned by [`Credentials::redacted`].
+#[derive(Debug, Clone, Copy)]
+pub struct RedactedCredentials<'a>(&'a Credentials);
+
+impl fmt::Display for RedactedCredentials<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let creds = self.0;
+        write!(
+            f,
+            "username={} password=*** subdomain={} fulldomain={} allowfrom=[{}]",
+            creds.username,
+            creds.subdomain,
+            creds.fulldomain,
+            creds.allowfrom.join(",")
+        )
+    }
+}
+
+#[cfg(feature = "client")]
 #[derive(Debug, Clone, Serialize)]
 struct RegistrationRequest<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     allowfrom: Option<&'a [String]>,
 }
 
+/// What [`AcmeDnsClient::register`] sends when there is no `allowfrom`;
+/// see [`AcmeDnsClientBuilder::register_body`].
+///
+/// Upstream acme-dns accepts all three, but some forks insist on one.
+/// With `allowfrom` the body is always JSON.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum RegisterBody {
+    /// `{}` with `Content-Type: application/json`.
+    #[default]
+    EmptyObject,
+    /// A zero-length body, still with `Content-Type: application/json`.
+    Empty,
+    /// No body and no `Content-Type`.
+    Omitted,
+}
+
+/// Which redirects the client follows; see
+/// [`AcmeDnsClientBuilder::redirect_policy`].
+///
+/// A redirect that is not followed is returned like any other unexpected
+/// response, as [`Error::UnexpectedStatus`] with the `3xx` status.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum RedirectPolicy {
+    /// Follow no redirects.
+    None,
+    /// Follow up to this many redirects, to any host.
+    Limited(usize),
+    /// Follow up to 10 redirects, but only while they stay on the scheme,
+    /// host and port of the original request, so the API key is never sent
+    /// elsewhere.
+    #[default]
+    SameHostOnly,
+}
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+impl RedirectPolicy {
+    pub(crate) fn to_reqwest(self) -> reqwest::redirect::Policy {
+        use reqwest::redirect::Policy;
+
+        match self {
+            RedirectPolicy::None => Policy::none(),
+            RedirectPolicy::Limited(max) => Policy::custom(move |attempt| {
+                if attempt.previous().len() > max {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }),
+            RedirectPolicy::SameHostOnly => Policy::custom(|attempt| {
+                let same_origin = attempt
+                    .previous()
+                    .first()
+                    .is_some_and(|first| first.origin() == attempt.url().origin());
+                if same_origin && attempt.previous().len() <= 10 {
+                    attempt.follow()
+                } else {
+                    attempt.stop()
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
 #[derive(Debug, Clone, Serialize)]
 struct UpdateRequest<'a> {
     subdomain: &'a str,
     txt: &'a str,
 }
 
+/// TXT value sent by [`AcmeDnsClient::verify_credentials`]: 43 base64url
+/// characters, the same shape as a real DNS-01 digest, so acme-dns accepts it.
+#[cfg(feature = "client")]
+const VERIFY_TXT: &str = "acme-dns-client-verify-credentials-00000000";
+
+/// TXT value sent by [`AcmeDnsClient::prime`]; 43 characters like
+/// [`VERIFY_TXT`].
+#[cfg(feature = "client")]
+const PRIME_TXT: &str = "acme-dns-client-placeholder-000000000000000";
+
+/// Default for [`AcmeDnsClientBuilder::max_response_size`]. acme-dns
+/// responses are a few hundred bytes.
+#[cfg(feature = "client")]
+pub(crate) const DEFAULT_MAX_RESPONSE_SIZE: usize = 16 * 1024;
+
 /// Minimal async client for the acme-dns HTTP API.
 ///
 /// It's intentionally tiny: you configure it with the API base URL,
 /// then call `register`, `update_txt`, and `health`.
+#[cfg(feature = "client")]
 #[derive(Clone, Debug)]
 pub struct AcmeDnsClient {
     base_url: Url,
     http: HttpClient,
+    client_ip_header: Option<(HeaderName, HeaderValue)>,
+    register_auth: Option<(HeaderName, HeaderValue)>,
+    json_content_type: Option<HeaderValue>,
+    expected_host: Option<String>,
+    bulk_update: bool,
+    lockout_check: Option<(Url, HttpClient)>,
+    register_idempotency_key: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    resolver: Arc<dyn DnsResolver>,
+    backoff: Option<Arc<dyn retry::NewBackoff>>,
+    dry_run: bool,
+    metrics: Option<Arc<dyn MetricsObserver>>,
+    on_credentials_changed: Option<CredentialsCallback>,
+    register_body: RegisterBody,
+    max_response_size: usize,
+    require_uuid_subdomain: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    har: Option<Arc<har::HarRecorder>>,
+    #[cfg(feature = "reqwest-middleware")]
+    middleware: Option<reqwest_middleware::ClientWithMiddleware>,
 }
 
+/// Callback set with [`AcmeDnsClientBuilder::on_credentials_changed`].
+#[cfg(feature = "client")]
+#[derive(Clone)]
+pub(crate) struct CredentialsCallback(pub(crate) Arc<dyn Fn(&Credentials) + Send + Sync>);
+
+#[cfg(feature = "client")]
+impl fmt::Debug for CredentialsCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CredentialsCallback(..)")
+    }
+}
+
+#[cfg(feature = "client")]
 impl AcmeDnsClient {
     /// Create a new client from the API base URL, e.g. `https://auth.example.org/`.
     pub fn new(base_url: impl AsRef<str>) -> Result<Self, Error> {
-        let base = Url::parse(base_url.as_ref())?;
-        let http = HttpClient::builder().build()?;
+        Self::builder(base_url).build()
+    }
+
+    /// Create a client that sends requests through an existing
+    /// `reqwest::Client`, sharing its connection pool.
+    pub fn with_client(base_url: impl AsRef<str>, http: HttpClient) -> Result<Self, Error> {
+        Self::builder(base_url).http_client(http).build()
+    }
+
+    /// Create a client that sends requests through a `reqwest-middleware`
+    /// stack; see [`AcmeDnsClientBuilder::middleware_client`].
+    #[cfg(feature = "reqwest-middleware")]
+    pub fn with_middleware_client(
+        base_url: impl AsRef<str>,
+        client: reqwest_middleware::ClientWithMiddleware,
+    ) -> Result<Self, Error> {
+        Self::builder(base_url).middleware_client(client).build()
+    }
+
+    /// A copy of this client pointed at `base_url`, reusing the same HTTP
+    /// client (and connection pool) and all other settings.
+    pub fn clone_with_base_url(&self, base_url: impl AsRef<str>) -> Result<Self, Error> {
         Ok(Self {
-            base_url: base,
-            http,
+            base_url: builder::parse_base_url(base_url.as_ref())?,
+            ..self.clone()
         })
     }
 
+    /// The API base URL, always ending in `/`.
+    ///
+    /// Together with [`http_client`](Self::http_client), this lets you call
+    /// endpoints of acme-dns forks that this crate has no method for.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// The HTTP client requests are sent with, including any default
+    /// headers and timeouts configured on the builder.
+    pub fn http_client(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// Start building a client with non-default settings.
+    pub fn builder(base_url: impl AsRef<str>) -> AcmeDnsClientBuilder {
+        AcmeDnsClientBuilder::new(base_url)
+    }
+
+    /// Use `resolver` for DNS lookups instead of the system resolver.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_resolver(mut self, resolver: impl DnsResolver + 'static) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
     /// Create a client from `ACME_DNS_API_BASE`.
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn from_env() -> Result<Self, Error> {
-        let base = std::env::var("ACME_DNS_API_BASE")
-            .map_err(|_| Error::MissingEnv("ACME_DNS_API_BASE"))?;
+        Self::from_env_prefixed(DEFAULT_ENV_PREFIX)
+    }
+
+    /// Like [`from_env`](Self::from_env), but reads `{prefix}_API_BASE`,
+    /// e.g. `PRIMARY_ACME_DNS_API_BASE` for the prefix `PRIMARY_ACME_DNS`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env_prefixed(prefix: &str) -> Result<Self, Error> {
+        let name = format!("{prefix}_API_BASE");
+        let base = std::env::var(&name).map_err(|_| missing_env_error(vec![name]))?;
         Self::new(base)
     }
 
+    /// Create a client from the `acme_dns_api_base` file in systemd's
+    /// `$CREDENTIALS_DIRECTORY`; see [`Credentials::from_systemd_creds`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_systemd_creds() -> Result<Self, Error> {
+        Self::new(required_systemd_credential("acme_dns_api_base")?)
+    }
+
     /// Register a new acme-dns account.
     ///
     /// If `allow_from` is provided, it configures CIDR ranges allowed to call `/update`.
     /// If `None`, the server default is used (often "no restriction" or “caller’s IP”). :contentReference[oaicite:2]{index=2}
+    ///
+    /// Any 2xx status is accepted as long as the body parses as credentials.
     pub async fn register(&self, allow_from: Option<&[String]>) -> Result<Credentials, Error> {
+        let (creds, _) = self.register_raw(allow_from).await?;
+        Ok(creds)
+    }
+
+    /// Like [`register`](Self::register), restricting `/update` to `nets`.
+    ///
+    /// Saves formatting networks you already hold as `ipnet::IpNet` values;
+    /// each is sent in its `Display` form, e.g. `10.0.0.0/8`.
+    #[cfg(feature = "ipnet")]
+    pub async fn register_with_nets(&self, nets: &[ipnet::IpNet]) -> Result<Credentials, Error> {
+        let cidrs: Vec<String> = nets.iter().map(ToString::to_string).collect();
+        self.register(Some(&cidrs)).await
+    }
+
+    /// Like [`register`](Self::register), but also return the response body
+    /// exactly as the server sent it, e.g. for an audit log.
+    pub async fn register_raw(
+        &self,
+        allow_from: Option<&[String]>,
+    ) -> Result<(Credentials, String), Error> {
+        let resp = self.register_response(allow_from).await?;
+        if self.dry_run {
+            return Err(Error::DryRun);
+        }
+
+        // Upstream answers 201, but some forks use 200; either way the body
+        // is the new account.
+        if !resp.status().is_success() {
+            return Err(self.status_error(resp).await);
+        }
+
+        let text = self.read_body(resp).await?;
+        let creds: Credentials = serde_json::from_str(&text)?;
+        self.credentials_changed(&creds);
+        Ok((creds, text))
+    }
+
+    /// Send `/register` and return the response as is, whatever its
+    /// status, for callers that want to read the body themselves.
+    ///
+    /// Retries, metrics and HAR recording apply as for
+    /// [`register`](Self::register), but the body is left unread, so
+    /// [`max_response_size`](AcmeDnsClientBuilder::max_response_size) does
+    /// not. Nothing is passed to
+    /// [`on_credentials_changed`](AcmeDnsClientBuilder::on_credentials_changed).
+    pub async fn register_response(
+        &self,
+        allow_from: Option<&[String]>,
+    ) -> Result<Response, Error> {
+        if let Some(cidrs) = allow_from {
+            cidrs.iter().try_for_each(|c| validate_cidr(c))?;
+            self.check_lockout(cidrs).await?;
+        }
         let url = self.base_url.join("register")?;
 
         let body = RegistrationRequest {
             allowfrom: allow_from,
         };
+        // One key per call, so that retries below share it.
+        let idempotency_key = self
+            .register_idempotency_key
+            .then(|| uuid::Uuid::new_v4().to_string());
+        let request = || {
+            let mut request = self.http.post(url.clone());
+            if let Some((name, value)) = &self.register_auth {
+                request = request.header(name, value);
+            }
+            if let Some(key) = &idempotency_key {
+                request = request.header("Idempotency-Key", key);
+            }
+            match (allow_from, self.register_body) {
+                (None, RegisterBody::Empty) => {
+                    let content_type = self
+                        .json_content_type
+                        .clone()
+                        .unwrap_or_else(|| HeaderValue::from_static("application/json"));
+                    request.header(CONTENT_TYPE, content_type).body("")
+                }
+                (None, RegisterBody::Omitted) => request,
+                _ => self.json(request, &body),
+            }
+        };
 
-        let resp = self.http.post(url).json(&body).send().await?;
-        let status = resp.status();
-        let text = resp.text().await?;
+        self.send("register", request).await
+    }
 
-        if status != StatusCode::CREATED {
-            return Err(Error::UnexpectedStatus { status, body: text });
-        }
+    /// [`register`](Self::register), giving up with [`Error::Timeout`] at
+    /// `deadline`.
+    ///
+    /// Unlike the builder's per-request
+    /// [`timeout`](AcmeDnsClientBuilder::timeout), the deadline covers the
+    /// whole call, retries included, so one deadline can be shared by
+    /// several steps. If it hits after the server created the account, that
+    /// account is lost.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn register_with_deadline(
+        &self,
+        allow_from: Option<&[String]>,
+        deadline: tokio::time::Instant,
+    ) -> Result<Credentials, Error> {
+        with_deadline(deadline, self.register(allow_from)).await
+    }
 
-        let creds: Credentials = serde_json::from_str(&text)?;
-        Ok(creds)
+    /// [`update_txt`](Self::update_txt), giving up with [`Error::Timeout`]
+    /// at `deadline`; see [`register_with_deadline`](Self::register_with_deadline).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn update_txt_with_deadline(
+        &self,
+        creds: &Credentials,
+        txt: &str,
+        deadline: tokio::time::Instant,
+    ) -> Result<(), Error> {
+        with_deadline(deadline, self.update_txt(creds, txt)).await
+    }
+
+    /// Register `count` accounts, running up to `concurrency` registrations
+    /// at a time, e.g. to provision many domains at once.
+    ///
+    /// Stops at the first error. Registrations still in flight at that
+    /// point are abandoned, so the server may have created accounts that are
+    /// not returned; use [`register_many_settled`](Self::register_many_settled)
+    /// to get every outcome instead.
+    pub async fn register_many(
+        &self,
+        count: usize,
+        allow_from: Option<&[String]>,
+        concurrency: usize,
+    ) -> Result<Vec<Credentials>, Error> {
+        stream::iter(0..count)
+            .map(|_| self.register(allow_from))
+            .buffer_unordered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Like [`register_many`](Self::register_many), but run all `count`
+    /// registrations and return each one's result.
+    pub async fn register_many_settled(
+        &self,
+        count: usize,
+        allow_from: Option<&[String]>,
+        concurrency: usize,
+    ) -> Vec<Result<Credentials, Error>> {
+        stream::iter(0..count)
+            .map(|_| self.register(allow_from))
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await
     }
 
     /// Update the TXT value associated with the given credentials.
@@ -108,321 +534,3969 @@ impl AcmeDnsClient {
     /// This is the call your ACME client makes every time the CA
     /// asks you to prove control via DNS-01. :contentReference[oaicite:3]{index=3}
     pub async fn update_txt(&self, creds: &Credentials, txt: &str) -> Result<(), Error> {
-        let url = self.base_url.join("update")?;
-
-        let body = UpdateRequest {
-            subdomain: &creds.subdomain,
-            txt,
-        };
-
-        let resp = self
-            .http
-            .post(url)
-            .header("X-Api-User", &creds.username)
-            .header("X-Api-Key", &creds.password)
-            .json(&body)
-            .send()
-            .await?;
+        self.set_txt_raw(creds, txt).await
+    }
 
-        let status = resp.status();
-        let text = resp.text().await?;
+    /// Like [`update_txt`](Self::update_txt), but also return details of
+    /// the response, such as the `X-Request-Id` a proxy added for log
+    /// correlation. On failure the same ID is available from
+    /// [`Error::request_id`].
+    pub async fn update_txt_with_meta(
+        &self,
+        creds: &Credentials,
+        txt: &str,
+    ) -> Result<ResponseMeta, Error> {
+        self.send_update(creds, txt, None).await
+    }
 
-        if status != StatusCode::OK {
-            return Err(Error::UnexpectedStatus { status, body: text });
-        }
+    /// Like [`update_txt`](Self::update_txt), but give up on this request
+    /// after `timeout` instead of the client's
+    /// [`timeout`](AcmeDnsClientBuilder::timeout), e.g. to fail fast and
+    /// retry while the rest of the client stays patient. The timeout covers
+    /// each attempt when [`retry`](AcmeDnsClientBuilder::retry) is set.
+    pub async fn update_txt_with_timeout(
+        &self,
+        creds: &Credentials,
+        txt: &str,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        self.send_update(creds, txt, Some(timeout)).await?;
+        Ok(())
+    }
 
+    /// Set the account's TXT record to an arbitrary `value`.
+    ///
+    /// This is the general-purpose variant of [`update_txt`](Self::update_txt)
+    /// for using acme-dns as a dynamic TXT store (service-discovery tokens
+    /// and the like) rather than for ACME challenges. The value is sent as
+    /// is, with no length or format checks; whether the server accepts it is
+    /// up to the server.
+    pub async fn set_txt_raw(&self, creds: &Credentials, value: &str) -> Result<(), Error> {
+        self.send_update(creds, value, None).await?;
         Ok(())
     }
 
-    /// Simple health check (`GET /health`).
-    pub async fn health(&self) -> Result<(), Error> {
-        let url = self.base_url.join("health")?;
-        let resp = self.http.get(url).send().await?;
-        let status = resp.status();
+    /// Send `/update` and return the response as is, whatever its status;
+    /// see [`register_response`](Self::register_response).
+    pub async fn update_response(&self, creds: &Credentials, txt: &str) -> Result<Response, Error> {
+        self.update_request(creds, txt, None).await
+    }
 
-        if status != StatusCode::OK {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(Error::UnexpectedStatus { status, body });
+    async fn send_update(
+        &self,
+        creds: &Credentials,
+        value: &str,
+        timeout: Option<Duration>,
+    ) -> Result<ResponseMeta, Error> {
+        let resp = self.update_request(creds, value, timeout).await?;
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
         }
 
-        Ok(())
+        Ok(ResponseMeta::from_headers(resp.headers()))
     }
-}
 
-impl Credentials {
-    /// Load credentials from environment variables.
-    ///
-    /// This mirrors the LEGO provider style a bit (API base is separate). :contentReference[oaicite:4]{index=4}
-    ///
-    /// Required:
-    ///   - `ACME_DNS_USERNAME`
-    ///   - `ACME_DNS_PASSWORD`
-    ///   - `ACME_DNS_SUBDOMAIN`
-    ///   - `ACME_DNS_FULLDOMAIN`
+    /// Set several TXT values on the account, e.g. the challenges for both
+    /// `example.com` and `*.example.com`.
     ///
-    /// Optional:
-    ///   - `ACME_DNS_ALLOWFROM` (comma-separated CIDRs)
-    pub fn from_env() -> Result<Self, Error> {
-        use std::env;
+    /// With [`bulk_update`](AcmeDnsClientBuilder::bulk_update) on, they go
+    /// in one `/update` whose body is a JSON array of
+    /// `{"subdomain", "txt"}` objects, which only forks of acme-dns that
+    /// accept that shape understand; upstream rejects it. Otherwise each
+    /// value is sent with its own [`update_txt`](Self::update_txt), in
+    /// order, stopping at the first failure. Upstream acme-dns keeps only
+    /// the last two values either way.
+    pub async fn update_txt_bulk(&self, creds: &Credentials, txts: &[&str]) -> Result<(), Error> {
+        if !self.bulk_update {
+            for txt in txts {
+                self.update_txt(creds, txt).await?;
+            }
+            return Ok(());
+        }
+        if txts.is_empty() {
+            return Ok(());
+        }
 
-        let username =
-            env::var("ACME_DNS_USERNAME").map_err(|_| Error::MissingEnv("ACME_DNS_USERNAME"))?;
-        let password =
-            env::var("ACME_DNS_PASSWORD").map_err(|_| Error::MissingEnv("ACME_DNS_PASSWORD"))?;
-        let subdomain =
-            env::var("ACME_DNS_SUBDOMAIN").map_err(|_| Error::MissingEnv("ACME_DNS_SUBDOMAIN"))?;
-        let fulldomain = env::var("ACME_DNS_FULLDOMAIN")
-            .map_err(|_| Error::MissingEnv("ACME_DNS_FULLDOMAIN"))?;
-
-        let allowfrom = env::var("ACME_DNS_ALLOWFROM")
-            .ok()
-            .map(|s| {
-                s.split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect()
+        let body: Vec<_> = txts
+            .iter()
+            .map(|txt| UpdateRequest {
+                subdomain: &creds.subdomain,
+                txt,
             })
-            .unwrap_or_default();
-
-        Ok(Self {
-            username,
-            password,
-            subdomain,
-            fulldomain,
-            allowfrom,
-        })
+            .collect();
+        let resp = self.post_update(creds, &body, None).await?;
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
+        }
+        Ok(())
     }
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use httpmock::prelude::*;
-    use serde_json::json;
-
-    #[tokio::test]
-    async fn register_parses_response() {
-        let server = MockServer::start();
 
-        let mock = server.mock(|when, then| {
-            when.method(POST).path("/register");
-            then.status(201).json_body(json!({
-                "allowfrom": ["192.168.100.1/24"],
-                "fulldomain": "8e57.auth.acme-dns.io",
-                "password": "pw",
-                "subdomain": "8e57",
-                "username": "user-uuid"
-            }));
-        });
+    async fn update_request(
+        &self,
+        creds: &Credentials,
+        value: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Response, Error> {
+        let body = UpdateRequest {
+            subdomain: &creds.subdomain,
+            txt: value,
+        };
+        self.post_update(creds, &body, timeout).await
+    }
 
-        let client = AcmeDnsClient::new(server.base_url()).unwrap();
-        let creds = client.register(None).await.unwrap();
+    /// Send `body` to `/update` with the account's API headers.
+    async fn post_update(
+        &self,
+        creds: &Credentials,
+        body: &impl Serialize,
+        timeout: Option<Duration>,
+    ) -> Result<Response, Error> {
+        if self.require_uuid_subdomain {
+            validate_subdomain(&creds.subdomain)?;
+        }
+        let url = self.base_url.join("update")?;
 
-        mock.assert();
-        assert_eq!(creds.username, "user-uuid");
-        assert_eq!(creds.password, "pw");
-        assert_eq!(creds.subdomain, "8e57");
-        assert_eq!(creds.fulldomain, "8e57.auth.acme-dns.io");
-        assert_eq!(creds.allowfrom, vec!["192.168.100.1/24"]);
+        self.send("update", || {
+            let request = self
+                .http
+                .post(url.clone())
+                .header("X-Api-User", &creds.username)
+                .header("X-Api-Key", &creds.password);
+            let request = self.json(request, body);
+            let request = match timeout {
+                Some(timeout) => request.timeout(timeout),
+                None => request,
+            };
+            match &self.client_ip_header {
+                Some((name, value)) => request.header(name, value),
+                None => request,
+            }
+        })
+        .await
     }
 
-    #[tokio::test]
-    async fn register_unexpected_status_errors() {
-        let server = MockServer::start();
-
-        let mock = server.mock(|when, then| {
-            when.method(POST).path("/register");
-            then.status(400).body("bad request");
-        });
+    /// Set `body` as the JSON body of `request`, with the
+    /// [`json_content_type`](AcmeDnsClientBuilder::json_content_type) if
+    /// one is configured.
+    fn json(&self, request: RequestBuilder, body: &impl Serialize) -> RequestBuilder {
+        // reqwest's `json` keeps a Content-Type that is already set.
+        match &self.json_content_type {
+            Some(content_type) => request.header(CONTENT_TYPE, content_type).json(body),
+            None => request.json(body),
+        }
+    }
 
-        let client = AcmeDnsClient::new(server.base_url()).unwrap();
-        let err = client.register(None).await.unwrap_err();
+    /// Read the body of `resp` as text, failing with
+    /// [`Error::ResponseTooLarge`] as soon as it exceeds
+    /// [`max_response_size`](AcmeDnsClientBuilder::max_response_size).
+    pub(crate) async fn read_body(&self, resp: Response) -> Result<String, Error> {
+        let limit = self.max_response_size;
+        let too_large = || Error::ResponseTooLarge { limit };
+        if resp.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(too_large());
+        }
 
-        mock.assert();
+        // reqwest on wasm32 cannot stream the body; the browser has it
+        // buffered by then anyway.
+        #[cfg(not(target_arch = "wasm32"))]
+        let body = {
+            let mut resp = resp;
+            let mut body = Vec::new();
+            while let Some(chunk) = resp.chunk().await? {
+                if body.len() + chunk.len() > limit {
+                    return Err(too_large());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            body
+        };
+        #[cfg(target_arch = "wasm32")]
+        let body = {
+            let body = resp.bytes().await?;
+            if body.len() > limit {
+                return Err(too_large());
+            }
+            body
+        };
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
 
-        let Error::UnexpectedStatus { status, body } = err else {
-            panic!("expected UnexpectedStatus, got {err:?}");
+    /// Turn a response with an unwanted status into an error.
+    ///
+    /// `401`/`403` mean the server rejected the request and become
+    /// [`Error::Unauthorized`], or [`Error::Forbidden`] when the body names
+    /// `allowfrom` as the reason; anything else is
+    /// [`Error::UnexpectedStatus`].
+    /// An oversized body is reported as [`Error::ResponseTooLarge`] instead.
+    pub(crate) async fn status_error(&self, resp: Response) -> Error {
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = match self.read_body(resp).await {
+            Ok(body) => body,
+            Err(e @ Error::ResponseTooLarge { .. }) => return e,
+            Err(_) => String::new(),
         };
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert_eq!(body, "bad request");
+        let request_id = error::request_id(&headers).map(str::to_string);
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return error::rejection(status, body, request_id);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return error::rate_limited(headers, body, request_id);
+        }
+        Error::UnexpectedStatus {
+            status,
+            headers,
+            body,
+        }
     }
 
-    #[tokio::test]
-    async fn update_sends_headers_and_body() {
-        let server = MockServer::start();
+    /// Replace the `allowfrom` CIDR ranges of an existing account.
+    ///
+    /// Sends `POST /allowfrom` with the account's API headers and a body of
+    /// `{"allowfrom": [...]}`, and returns `creds` with the new ranges on a
+    /// `200`. Upstream acme-dns has no such endpoint (allowfrom is fixed at
+    /// registration), so this only works against deployments that add it;
+    /// elsewhere expect [`Error::UnexpectedStatus`] with a 404. In dry-run
+    /// mode the new ranges are returned but not reported to
+    /// [`on_credentials_changed`](AcmeDnsClientBuilder::on_credentials_changed).
+    pub async fn update_allowfrom(
+        &self,
+        creds: &Credentials,
+        cidrs: &[String],
+    ) -> Result<Credentials, Error> {
+        cidrs.iter().try_for_each(|c| validate_cidr(c))?;
+        let url = self.base_url.join("allowfrom")?;
 
-        let mock = server.mock(|when, then| {
-            when.method(POST)
-                .path("/update")
-                .header("X-Api-User", "user-uuid")
-                .header("X-Api-Key", "pw")
-                .json_body(json!({
-                    "subdomain": "8e57",
-                    "txt": "token123"
-                }));
+        let body = RegistrationRequest {
+            allowfrom: Some(cidrs),
+        };
 
-            then.status(200).body("OK");
-        });
+        let resp = self
+            .send("allowfrom", || {
+                let request = self
+                    .http
+                    .post(url.clone())
+                    .header("X-Api-User", &creds.username)
+                    .header("X-Api-Key", &creds.password);
+                self.json(request, &body)
+            })
+            .await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
+        }
 
-        let client = AcmeDnsClient::new(server.base_url()).unwrap();
         let creds = Credentials {
-            username: "user-uuid".into(),
-            password: "pw".into(),
-            subdomain: "8e57".into(),
-            fulldomain: "8e57.auth.acme-dns.io".into(),
-            allowfrom: vec![],
+            allowfrom: cidrs.to_vec(),
+            ..creds.clone()
         };
-
-        client.update_txt(&creds, "token123").await.unwrap();
-        mock.assert();
+        self.credentials_changed(&creds);
+        Ok(creds)
     }
 
-    #[tokio::test]
-    async fn update_unexpected_status_errors() {
-        let server = MockServer::start();
+    /// Ask the server which TXT values it has stored for the account,
+    /// bypassing DNS and its caches.
+    ///
+    /// Sends `GET /txt` with the account's API headers and expects `200`
+    /// with `{"txt": [...]}` (or a bare JSON array). Upstream acme-dns has
+    /// no such endpoint, so this only works against deployments that add
+    /// one; elsewhere expect [`Error::UnexpectedStatus`] with a 404. Use
+    /// [`current_txt`](Self::current_txt) to see what DNS serves instead.
+    /// In dry-run mode the result is [`Error::DryRun`].
+    pub async fn get_stored_txt(&self, creds: &Credentials) -> Result<Vec<String>, Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum StoredTxt {
+            Object { txt: Vec<String> },
+            List(Vec<String>),
+        }
 
-        let mock = server.mock(|when, then| {
-            when.method(POST).path("/update");
-            then.status(400).body("bad_txt");
-        });
+        let url = self.base_url.join("txt")?;
+        let resp = self
+            .send("txt", || {
+                self.http
+                    .get(url.clone())
+                    .header("X-Api-User", &creds.username)
+                    .header("X-Api-Key", &creds.password)
+            })
+            .await?;
+        if self.dry_run {
+            return Err(Error::DryRun);
+        }
 
-        let client = AcmeDnsClient::new(server.base_url()).unwrap();
-        let creds = Credentials {
-            username: "user-uuid".into(),
-            password: "pw".into(),
-            subdomain: "8e57".into(),
-            fulldomain: "8e57.auth.acme-dns.io".into(),
-            allowfrom: vec![],
-        };
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
+        }
 
-        let err = client.update_txt(&creds, "token123").await.unwrap_err();
+        let body = self.read_body(resp).await?;
+        Ok(match serde_json::from_str(&body)? {
+            StoredTxt::Object { txt } | StoredTxt::List(txt) => txt,
+        })
+    }
 
-        mock.assert();
+    /// Check that `creds` are accepted by the server.
+    ///
+    /// acme-dns has no read-only endpoint for this, so it performs a real
+    /// `/update` with a harmless placeholder value: **the TXT record is
+    /// overwritten**. Don't call it while a challenge is pending.
+    ///
+    /// A `401` or `403` becomes [`Error::Unauthorized`].
+    pub async fn verify_credentials(&self, creds: &Credentials) -> Result<(), Error> {
+        self.update_txt(creds, VERIFY_TXT).await
+    }
 
-        let Error::UnexpectedStatus { status, body } = err else {
-            panic!("expected UnexpectedStatus, got {err:?}");
-        };
+    /// Make sure the account's TXT record exists before the first challenge
+    /// by setting a placeholder value.
+    ///
+    /// A fresh acme-dns subdomain has no TXT record, so a resolver that looks
+    /// it up early (e.g. a premature propagation check) gets NXDOMAIN and
+    /// caches that for the zone's negative TTL (the SOA minimum), which can
+    /// hide the real value for minutes after it is set. Priming turns that
+    /// into a NOERROR answer whose cache entry expires with the record's
+    /// own, usually short, TTL.
+    ///
+    /// The placeholder has the 43-character shape of a DNS-01 digest so
+    /// acme-dns accepts it. Like [`verify_credentials`](Self::verify_credentials),
+    /// this overwrites the current value.
+    pub async fn prime(&self, creds: &Credentials) -> Result<(), Error> {
+        self.update_txt(creds, PRIME_TXT).await
+    }
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert_eq!(body, "bad_txt");
+    /// Publish the DNS-01 TXT value derived from `key_authorization`.
+    ///
+    /// Use this when your ACME library gives you the key authorization
+    /// (`<token>.<thumbprint>`) rather than the final TXT value; the digest is
+    /// computed with [`dns01_txt_value`].
+    pub async fn update_txt_for_key_auth(
+        &self,
+        creds: &Credentials,
+        key_authorization: &str,
+    ) -> Result<(), Error> {
+        self.update_txt(creds, &dns01_txt_value(key_authorization))
+            .await
     }
 
-    #[tokio::test]
-    async fn health_ok() {
-        let server = MockServer::start();
+    /// Simple health check (`GET /health`).
+    pub async fn health(&self) -> Result<(), Error> {
+        let resp = self.health_response().await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(self.status_error(resp).await);
+        }
+
+        Ok(())
+    }
+
+    /// Send `GET /health` and return the response as is, whatever its
+    /// status; see [`register_response`](Self::register_response).
+    pub async fn health_response(&self) -> Result<Response, Error> {
+        let url = self.base_url.join("health")?;
+        self.send("health", || self.http.get(url.clone())).await
+    }
+
+    /// Load credentials from `creds_path` if it exists, otherwise register a
+    /// new account and save it there.
+    ///
+    /// Existing credentials are only returned once `/health` succeeds, so a
+    /// bootstrap script that runs twice reuses the first account instead of
+    /// creating a duplicate. acme-dns has no side-effect–free way to check a
+    /// password, so the credentials themselves are not re-validated.
+    pub async fn register_if_absent(
+        &self,
+        creds_path: impl AsRef<Path>,
+        allow_from: Option<&[String]>,
+    ) -> Result<Credentials, Error> {
+        let creds_path = creds_path.as_ref();
+
+        if creds_path.exists() {
+            let creds = Credentials::load_from_path(creds_path)?;
+            self.health().await?;
+            return Ok(creds);
+        }
+
+        let creds = self.register(allow_from).await?;
+        creds.save_to_path(creds_path)?;
+        Ok(creds)
+    }
+
+    /// Report `creds` to the `on_credentials_changed` callback, unless in
+    /// dry-run mode, where the server never saw the change.
+    fn credentials_changed(&self, creds: &Credentials) {
+        if self.dry_run {
+            return;
+        }
+        if let Some(callback) = &self.on_credentials_changed {
+            (callback.0)(creds);
+        }
+    }
+
+    /// Send the request built by `request`, retrying transient failures
+    /// according to the configured [`Backoff`].
+    ///
+    /// Returns the last response once it is not retryable or retries run out;
+    /// checking the status is left to the caller. In dry-run mode the request
+    /// is logged and an empty `200 OK` is returned instead.
+    async fn send(
+        &self,
+        endpoint: &str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, Error> {
+        self.check_host()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.dry_run {
+            log_dry_run(&request().build()?);
+            return Ok(http::Response::new(String::new()).into());
+        }
+
+        let mut backoff = self.backoff.as_ref().map(|b| b.fresh());
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let result = self.execute(request()).await;
+            if let Some(metrics) = &self.metrics {
+                let status = result.as_ref().ok().map(|resp| resp.status());
+                metrics.on_request(endpoint, status, started.elapsed());
+            }
+
+            let Some(backoff) = backoff.as_mut() else {
+                return result;
+            };
+            match &result {
+                Ok(resp) if error::is_retryable_status(resp.status()) => {}
+                Err(e) if e.is_retryable() => {}
+                _ => return result,
+            }
+            let Some(delay) = backoff.next_delay(attempt) else {
+                return result;
+            };
+            // A 429 may give its delay in the body, which is read here as the
+            // response is being retried and not returned.
+            let retry_after = match result {
+                Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    self.status_error(resp).await.retry_after()
+                }
+                Ok(resp) => retry::retry_after(resp.headers()),
+                Err(_) => None,
+            };
+
+            timer::sleep(retry_after.unwrap_or(delay)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Fail with [`Error::WouldLockOut`] if `cidrs` leaves out this host's
+    /// public address; see
+    /// [`check_allowfrom_lockout`](AcmeDnsClientBuilder::check_allowfrom_lockout).
+    async fn check_lockout(&self, cidrs: &[String]) -> Result<(), Error> {
+        let Some((service, http)) = &self.lockout_check else {
+            return Ok(());
+        };
+        // A dry run sends nothing, not even the IP check.
+        if cidrs.is_empty() || self.dry_run {
+            return Ok(());
+        }
+
+        let resp = http.get(service.clone()).send().await?;
+        if !resp.status().is_success() {
+            return Err(Error::IpCheck(format!(
+                "{service} answered {}",
+                resp.status()
+            )));
+        }
+        let body = self.read_body(resp).await?;
+        let ip: IpAddr = body
+            .trim()
+            .parse()
+            .map_err(|_| Error::IpCheck(format!("{service} answered {:?}", body.trim())))?;
+
+        let covered = cidrs.iter().any(|cidr| cidr_contains(cidr, ip));
+        if covered {
+            Ok(())
+        } else {
+            tracing::warn!(%ip, ?cidrs, "allowfrom would lock this host out");
+            Err(Error::WouldLockOut {
+                ip,
+                allowfrom: cidrs.to_vec(),
+            })
+        }
+    }
+
+    /// Fail with [`Error::HostMismatch`] if the base URL is not on the
+    /// [`expected_host`](AcmeDnsClientBuilder::expected_host).
+    fn check_host(&self) -> Result<(), Error> {
+        let Some(expected) = &self.expected_host else {
+            return Ok(());
+        };
+        let actual = self.base_url.host_str().unwrap_or("");
+        let normalize = |host: &str| host.trim_end_matches('.').to_ascii_lowercase();
+        if normalize(actual) == normalize(expected) {
+            Ok(())
+        } else {
+            Err(Error::HostMismatch {
+                expected: expected.clone(),
+                actual: actual.to_string(),
+            })
+        }
+    }
+
+    /// Send one request, through the middleware stack if there is one, and
+    /// record it if [`record_har`](AcmeDnsClientBuilder::record_har) is on.
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let request = request.build()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(har) = &self.har {
+            let pending = har.start(&request);
+            let resp = self.execute_request(request).await?;
+            return har.finish(pending, resp, self.max_response_size).await;
+        }
+        self.execute_request(request).await
+    }
+
+    async fn execute_request(&self, request: reqwest::Request) -> Result<Response, Error> {
+        #[cfg(feature = "reqwest-middleware")]
+        if let Some(middleware) = &self.middleware {
+            return Ok(middleware.execute(request).await?);
+        }
+        Ok(self.http.execute(request).await?)
+    }
+
+    /// Resolve the TXT values currently served at `creds.fulldomain`.
+    ///
+    /// Compare the result against the value you are about to set to skip a
+    /// redundant [`update_txt`](Self::update_txt). A name that does not exist
+    /// yet (NXDOMAIN) yields an empty vec.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn current_txt(&self, creds: &Credentials) -> Result<Vec<String>, Error> {
+        match self.resolver.lookup_txt(&creds.fulldomain).await {
+            Err(Error::NxDomain(_)) => Ok(Vec::new()),
+            other => other,
+        }
+    }
+
+    /// Like [`update_txt`](Self::update_txt), but skip the `/update` when
+    /// DNS already serves `txt` for this account (checked with
+    /// [`current_txt`](Self::current_txt)).
+    ///
+    /// Saves writes and log noise on a shared acme-dns when the same value
+    /// is set repeatedly. Resolver caching can make the check report a value
+    /// that was just replaced; point the client at the acme-dns nameserver
+    /// with [`AcmeDnsClientBuilder::nameserver`] to avoid that.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn update_txt_if_changed(
+        &self,
+        creds: &Credentials,
+        txt: &str,
+    ) -> Result<UpdateOutcome, Error> {
+        if self.current_txt(creds).await?.iter().any(|v| v == txt) {
+            return Ok(UpdateOutcome::AlreadySet);
+        }
+        self.update_txt(creds, txt).await?;
+        Ok(UpdateOutcome::Updated)
+    }
+
+    /// Set `new` only if DNS currently serves `expected`, or, with `None`,
+    /// serves no TXT value at all; otherwise fail with
+    /// [`Error::Precondition`] without sending anything.
+    ///
+    /// acme-dns serves the last two values set, so `expected` only has to be
+    /// one of them. This guards against clobbering a concurrent updater,
+    /// within the limits of a check-then-set: the same resolver caching
+    /// caveat as [`update_txt_if_changed`](Self::update_txt_if_changed)
+    /// applies, and nothing stops an update landing between the lookup and
+    /// the `/update`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn update_txt_compare_and_set(
+        &self,
+        creds: &Credentials,
+        expected: Option<&str>,
+        new: &str,
+    ) -> Result<(), Error> {
+        let current = self.current_txt(creds).await?;
+        let matches = match expected {
+            Some(expected) => current.iter().any(|v| v == expected),
+            None => current.is_empty(),
+        };
+        if !matches {
+            return Err(Error::Precondition {
+                expected: expected.map(str::to_string),
+                found: current,
+            });
+        }
+        self.update_txt(creds, new).await
+    }
+}
+
+/// What [`AcmeDnsClient::update_txt_if_changed`] did.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// The value was sent with `/update`.
+    Updated,
+    /// DNS already served the value, so nothing was sent.
+    AlreadySet,
+}
+
+/// Details of a successful response, from
+/// [`AcmeDnsClient::update_txt_with_meta`].
+#[cfg(feature = "client")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ResponseMeta {
+    /// The `X-Request-Id` response header, if the server or a proxy in
+    /// front of it sent one.
+    pub request_id: Option<String>,
+}
+
+#[cfg(feature = "client")]
+impl ResponseMeta {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        Self {
+            request_id: error::request_id(headers).map(str::to_string),
+        }
+    }
+}
+
+/// Check that `cidr` is a network in CIDR notation, e.g. `192.168.100.0/24`.
+///
+/// acme-dns rejects anything else in `allowfrom`, including bare addresses.
+pub fn validate_cidr(cidr: &str) -> Result<(), Error> {
+    parse_cidr(cidr)
+        .map(|_| ())
+        .ok_or_else(|| Error::InvalidCidr(cidr.to_string()))
+}
+
+/// `cidr` split into its address and prefix length, if it is in CIDR
+/// notation. Host bits may be set, as acme-dns allows.
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = cidr.split_once('/')?;
+    let addr: IpAddr = addr.parse().ok()?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let prefix = prefix.parse().ok().filter(|p| *p <= max)?;
+    Some((addr, prefix))
+}
+
+/// Whether the network `cidr` contains `ip`; false if `cidr` does not
+/// parse or is of the other address family.
+fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+    match (parse_cidr(cidr), ip) {
+        (Some((IpAddr::V4(net), prefix)), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (Some((IpAddr::V6(net), prefix)), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Check that `subdomain` is a UUID, e.g.
+/// `8e5700ea-a4bf-41c7-8a77-e990661dcc6a`, as upstream acme-dns issues.
+///
+/// Catches passing the `fulldomain` where the subdomain belongs; see
+/// [`AcmeDnsClientBuilder::require_uuid_subdomain`].
+pub fn validate_subdomain(subdomain: &str) -> Result<(), Error> {
+    let groups: Vec<&str> = subdomain.split('-').collect();
+    let is_uuid = groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|g| g.bytes().all(|b| b.is_ascii_hexdigit()));
+    if is_uuid {
+        Ok(())
+    } else {
+        Err(Error::InvalidSubdomain(subdomain.to_string()))
+    }
+}
+
+/// Prefix of the variables [`Credentials::from_env`] and
+/// [`AcmeDnsClient::from_env`] read.
+const DEFAULT_ENV_PREFIX: &str = "ACME_DNS";
+
+/// Variables [`Credentials::from_env`] cannot do without, after the prefix.
+#[cfg(not(target_arch = "wasm32"))]
+const REQUIRED_ENV: [&str; 4] = ["USERNAME", "PASSWORD", "SUBDOMAIN", "FULLDOMAIN"];
+
+/// The [`REQUIRED_ENV`] variables under [`DEFAULT_ENV_PREFIX`].
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_REQUIRED_ENV: [&str; 4] = [
+    "ACME_DNS_USERNAME",
+    "ACME_DNS_PASSWORD",
+    "ACME_DNS_SUBDOMAIN",
+    "ACME_DNS_FULLDOMAIN",
+];
+
+/// [`Error::MissingEnv`] for a single default variable, otherwise
+/// [`Error::MissingEnvVars`].
+#[cfg(not(target_arch = "wasm32"))]
+fn missing_env_error(missing: Vec<String>) -> Error {
+    let mut defaults = std::iter::once("ACME_DNS_API_BASE").chain(DEFAULT_REQUIRED_ENV);
+    match defaults.find(|name| missing == [*name]) {
+        Some(name) => Error::MissingEnv(name),
+        None => Error::MissingEnvVars(missing),
+    }
+}
+
+/// Names of the [`REQUIRED_ENV`] variables under `prefix` that are not set.
+#[cfg(not(target_arch = "wasm32"))]
+fn missing_env(prefix: &str) -> Result<(), Vec<String>> {
+    let missing: Vec<_> = REQUIRED_ENV
+        .into_iter()
+        .map(|suffix| format!("{prefix}_{suffix}"))
+        .filter(|name| std::env::var(name).is_err())
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+/// Run `operation`, or fail with [`Error::Timeout`] once `deadline` passes.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+async fn with_deadline<T>(
+    deadline: tokio::time::Instant,
+    operation: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    tokio::time::timeout_at(deadline, operation)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+/// Split a comma-separated CIDR list, dropping blanks.
+#[cfg(not(target_arch = "wasm32"))]
+fn split_cidrs(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Read the file `name` from `$CREDENTIALS_DIRECTORY`, or `None` if it
+/// does not exist.
+#[cfg(not(target_arch = "wasm32"))]
+fn systemd_credential(name: &str) -> Result<Option<String>, Error> {
+    let dir = std::env::var_os("CREDENTIALS_DIRECTORY")
+        .ok_or(Error::MissingEnv("CREDENTIALS_DIRECTORY"))?;
+    match std::fs::read_to_string(Path::new(&dir).join(name)) {
+        Ok(value) => Ok(Some(
+            value
+                .strip_suffix('\n')
+                .map(str::to_string)
+                .unwrap_or(value),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn required_systemd_credential(name: &'static str) -> Result<String, Error> {
+    systemd_credential(name)?.ok_or(Error::MissingCredential(name))
+}
+
+/// Log what `request` would have sent, with the API key redacted.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+fn log_dry_run(request: &reqwest::Request) {
+    let headers: Vec<_> = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == "x-api-key" || value.is_sensitive() {
+                "***"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{name}: {value}")
+        })
+        .collect();
+    let body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+
+    tracing::info!(
+        method = %request.method(),
+        url = %request.url(),
+        headers = ?headers,
+        body = %body,
+        "dry run: request not sent"
+    );
+}
+
+impl Credentials {
+    /// Start building credentials field by field.
+    pub fn builder() -> CredentialsBuilder {
+        CredentialsBuilder::default()
+    }
+
+    /// A view of these credentials that is safe to print: the password is
+    /// replaced with `***`.
+    pub fn redacted(&self) -> RedactedCredentials<'_> {
+        RedactedCredentials(self)
+    }
+
+    /// The acme-dns zone this account lives in: `fulldomain` without the
+    /// leading `subdomain.`, e.g. `auth.acme-dns.io` for
+    /// `8e57.auth.acme-dns.io`.
+    ///
+    /// Returns `None` if `fulldomain` does not start with `subdomain.`.
+    pub fn zone(&self) -> Option<&str> {
+        let zone = self
+            .fulldomain
+            .strip_prefix(self.subdomain.as_str())?
+            .strip_prefix('.')?
+            .trim_end_matches('.');
+        (!zone.is_empty()).then_some(zone)
+    }
+
+    /// `allowfrom` parsed into networks.
+    ///
+    /// Fails with [`Error::InvalidCidr`] on the first entry that is not in
+    /// CIDR notation.
+    #[cfg(feature = "ipnet")]
+    pub fn allowfrom_nets(&self) -> Result<Vec<ipnet::IpNet>, Error> {
+        self.allowfrom
+            .iter()
+            .map(|cidr| {
+                cidr.parse()
+                    .map_err(|_| Error::InvalidCidr(cidr.to_string()))
+            })
+            .collect()
+    }
+
+    /// Whether acme-dns would accept an `/update` for this account from
+    /// `ip`, e.g. to check the current outbound address before trying.
+    ///
+    /// An empty `allowfrom` allows every address. Entries that are not
+    /// valid CIDRs match nothing.
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        self.allowfrom.is_empty() || self.allowfrom.iter().any(|cidr| cidr_contains(cidr, ip))
+    }
+
+    /// The DNS record to create so that `challenge_domain` delegates its
+    /// DNS-01 challenges to this account, in zone-file syntax:
+    ///
+    /// ```text
+    /// _acme-challenge.<challenge_domain>. CNAME <fulldomain>.
+    /// ```
+    ///
+    /// A wildcard `challenge_domain` is delegated through its base domain's
+    /// record; see [`challenge_fqdn`].
+    pub fn cname_instructions(&self, challenge_domain: &str) -> String {
+        format!(
+            "{}. CNAME {}.",
+            challenge_fqdn(challenge_domain),
+            self.fulldomain.trim_end_matches('.')
+        )
+    }
+
+    /// Load credentials from environment variables.
+    ///
+    /// This mirrors the LEGO provider style a bit (API base is separate). :contentReference[oaicite:4]{index=4}
+    ///
+    /// Required:
+    ///   - `ACME_DNS_USERNAME`
+    ///   - `ACME_DNS_PASSWORD`
+    ///   - `ACME_DNS_SUBDOMAIN`
+    ///   - `ACME_DNS_FULLDOMAIN`
+    ///
+    /// Optional:
+    ///   - `ACME_DNS_ALLOWFROM` (comma-separated CIDRs)
+    ///
+    /// If one required variable is missing this is [`Error::MissingEnv`];
+    /// if several are, [`Error::MissingEnvVars`] lists them all.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_env_prefixed(DEFAULT_ENV_PREFIX)
+    }
+
+    /// Like [`from_env`](Self::from_env), but with `prefix` in place of
+    /// `ACME_DNS`, e.g. `PRIMARY_ACME_DNS_USERNAME` for the prefix
+    /// `PRIMARY_ACME_DNS`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env_prefixed(prefix: &str) -> Result<Self, Error> {
+        use std::env;
+
+        missing_env(prefix).map_err(missing_env_error)?;
+
+        let var = |suffix: &str| {
+            let name = format!("{prefix}_{suffix}");
+            env::var(&name).map_err(|_| missing_env_error(vec![name]))
+        };
+        let username = var("USERNAME")?;
+        let password = var("PASSWORD")?;
+        let subdomain = var("SUBDOMAIN")?;
+        let fulldomain = var("FULLDOMAIN")?;
+
+        let allowfrom = var("ALLOWFROM")
+            .map(|s| split_cidrs(&s))
+            .unwrap_or_default();
+
+        Ok(Self {
+            username,
+            password,
+            subdomain,
+            fulldomain,
+            allowfrom,
+        })
+    }
+
+    /// Check that every variable [`from_env`](Self::from_env) requires is
+    /// set, returning all missing names at once.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn check_env() -> Result<(), Vec<&'static str>> {
+        let missing: Vec<_> = DEFAULT_REQUIRED_ENV
+            .into_iter()
+            .filter(|name| std::env::var(name).is_err())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Override fields with the `ACME_DNS_*` variables that are set,
+    /// leaving the rest as they are, e.g. to load an account from a file
+    /// and take the password from a secret injected at runtime.
+    ///
+    /// Reads the same variables as [`from_env`](Self::from_env), none of
+    /// them required.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn merge_env(&mut self) {
+        let var = |suffix: &str| std::env::var(format!("{DEFAULT_ENV_PREFIX}_{suffix}")).ok();
+        for (suffix, field) in [
+            ("USERNAME", &mut self.username),
+            ("PASSWORD", &mut self.password),
+            ("SUBDOMAIN", &mut self.subdomain),
+            ("FULLDOMAIN", &mut self.fulldomain),
+        ] {
+            if let Some(value) = var(suffix) {
+                *field = value;
+            }
+        }
+        if let Some(cidrs) = var("ALLOWFROM") {
+            self.allowfrom = split_cidrs(&cidrs);
+        }
+    }
+
+    /// `export ACME_DNS_...=...` lines for every field, single-quoted for
+    /// `eval` in a POSIX shell, so that later commands can load the
+    /// account with [`from_env`](Self::from_env). `allowfrom` is joined
+    /// with commas, as `ACME_DNS_ALLOWFROM` expects.
+    ///
+    /// The output contains the password.
+    pub fn to_env_exports(&self) -> String {
+        let allowfrom = self.allowfrom.join(",");
+        [
+            ("USERNAME", self.username.as_str()),
+            ("PASSWORD", &self.password),
+            ("SUBDOMAIN", &self.subdomain),
+            ("FULLDOMAIN", &self.fulldomain),
+            ("ALLOWFROM", &allowfrom),
+        ]
+        .iter()
+        .map(|(suffix, value)| {
+            // Inside single quotes only `'` itself needs escaping.
+            let quoted = value.replace('\'', r"'\''");
+            format!("export {DEFAULT_ENV_PREFIX}_{suffix}='{quoted}'\n")
+        })
+        .collect()
+    }
+
+    /// Load credentials from systemd's `$CREDENTIALS_DIRECTORY` (see
+    /// `LoadCredential=` in systemd.exec(5)), one file per field.
+    ///
+    /// Unlike [`from_env`](Self::from_env), this keeps the password out of
+    /// the environment, where it would be readable via `/proc`.
+    ///
+    /// Required files:
+    ///   - `acme_dns_username`
+    ///   - `acme_dns_password`
+    ///   - `acme_dns_subdomain`
+    ///   - `acme_dns_fulldomain`
+    ///
+    /// Optional:
+    ///   - `acme_dns_allowfrom` (comma-separated CIDRs)
+    ///
+    /// A single trailing newline is stripped from each file. A missing file
+    /// is reported as [`Error::MissingCredential`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_systemd_creds() -> Result<Self, Error> {
+        let allowfrom = systemd_credential("acme_dns_allowfrom")?
+            .map(|s| split_cidrs(&s))
+            .unwrap_or_default();
+
+        Ok(Self {
+            username: required_systemd_credential("acme_dns_username")?,
+            password: required_systemd_credential("acme_dns_password")?,
+            subdomain: required_systemd_credential("acme_dns_subdomain")?,
+            fulldomain: required_systemd_credential("acme_dns_fulldomain")?,
+            allowfrom,
+        })
+    }
+
+    /// Parse credentials from JSON in the `/register` response shape, e.g. a
+    /// blob fetched from a secrets manager.
+    pub fn from_json_str(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Like [`from_json_str`](Self::from_json_str), for raw bytes.
+    pub fn from_json_slice(json: &[u8]) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    /// Load credentials previously written by [`save_to_path`](Self::save_to_path).
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_json_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Save credentials as pretty-printed JSON (the same shape `/register`
+    /// returns).
+    ///
+    /// On Unix the file is created with mode `0600`, since it holds the
+    /// account password.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        write_private(
+            path.as_ref(),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+}
+
+/// Write `contents` to `path`, readable only by the owner on Unix.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+/// Builder for [`Credentials`], for when they come from somewhere other than
+/// a `/register` response, the environment or a file.
+///
+/// ```
+/// # use acme_dns_client::Credentials;
+/// let creds = Credentials::builder()
+///     .username("user-uuid")
+///     .password("secret")
+///     .subdomain("8e57")
+///     .fulldomain("8e57.auth.example.org")
+///     .build()?;
+/// # Ok::<(), acme_dns_client::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct CredentialsBuilder {
+    username: Option<String>,
+    password: Option<String>,
+    subdomain: Option<String>,
+    fulldomain: Option<String>,
+    allowfrom: Vec<String>,
+}
+
+impl CredentialsBuilder {
+    /// The account's API user (`X-Api-User`). Required.
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// The account's API key (`X-Api-Key`). Required.
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// The account's subdomain, as sent in `/update`. Required.
+    pub fn subdomain(mut self, subdomain: impl Into<String>) -> Self {
+        self.subdomain = Some(subdomain.into());
+        self
+    }
+
+    /// The full name the TXT record is served at. Required.
+    pub fn fulldomain(mut self, fulldomain: impl Into<String>) -> Self {
+        self.fulldomain = Some(fulldomain.into());
+        self
+    }
+
+    /// CIDR ranges allowed to call `/update`. Empty by default.
+    pub fn allowfrom<S: Into<String>>(mut self, cidrs: impl IntoIterator<Item = S>) -> Self {
+        self.allowfrom = cidrs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Build the credentials, or [`Error::MissingField`] naming the first
+    /// required field that was not set.
+    pub fn build(self) -> Result<Credentials, Error> {
+        Ok(Credentials {
+            username: self.username.ok_or(Error::MissingField("username"))?,
+            password: self.password.ok_or(Error::MissingField("password"))?,
+            subdomain: self.subdomain.ok_or(Error::MissingField("subdomain"))?,
+            fulldomain: self.fulldomain.ok_or(Error::MissingField("fulldomain"))?,
+            allowfrom: self.allowfrom,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use reqwest::header::HeaderMap;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Debug, Default)]
+    struct StubResolver {
+        records: HashMap<String, Vec<String>>,
+    }
+
+    impl StubResolver {
+        fn with(mut self, name: &str, values: &[&str]) -> Self {
+            self.records.insert(
+                name.to_string(),
+                values.iter().map(|v| v.to_string()).collect(),
+            );
+            self
+        }
+    }
+
+    impl DnsResolver for StubResolver {
+        fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move {
+                self.records
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| Error::NxDomain(name.to_string()))
+            })
+        }
+    }
+
+    pub(crate) fn test_creds() -> Credentials {
+        Credentials {
+            username: "user-uuid".into(),
+            password: "pw".into(),
+            subdomain: "8e57".into(),
+            fulldomain: "8e57.auth.acme-dns.io".into(),
+            allowfrom: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn register_parses_response() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "allowfrom": ["192.168.100.1/24"],
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register(None).await.unwrap();
+
+        mock.assert();
+        assert_eq!(creds.username, "user-uuid");
+        assert_eq!(creds.password, "pw");
+        assert_eq!(creds.subdomain, "8e57");
+        assert_eq!(creds.fulldomain, "8e57.auth.acme-dns.io");
+        assert_eq!(creds.allowfrom, vec!["192.168.100.1/24"]);
+    }
+
+    #[cfg(feature = "ipnet")]
+    #[tokio::test]
+    async fn register_with_nets_sends_cidr_strings() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/register")
+                .json_body(json!({ "allowfrom": ["10.0.0.0/8", "2001:db8::/32"] }));
+            then.status(201).json_body(json!({
+                "allowfrom": ["10.0.0.0/8", "2001:db8::/32"],
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+
+        let nets: Vec<ipnet::IpNet> = vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:0db8:0000::/32".parse().unwrap(),
+        ];
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register_with_nets(&nets).await.unwrap();
+
+        mock.assert();
+        assert_eq!(creds.allowfrom, vec!["10.0.0.0/8", "2001:db8::/32"]);
+    }
+
+    #[tokio::test]
+    async fn register_raw_returns_body_verbatim() {
+        let server = MockServer::start();
+        let body = r#"{ "username":"user-uuid", "password":"pw",
+            "fulldomain":"8e57.auth.acme-dns.io","subdomain":"8e57", "extra": 1 }"#;
+
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).body(body);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let (creds, raw) = client.register_raw(None).await.unwrap();
+
+        assert_eq!(raw, body);
+        assert_eq!(creds.subdomain, "8e57");
+    }
+
+    #[tokio::test]
+    async fn register_body_modes_send_exact_bytes() {
+        let server = MockServer::start_async().await;
+        let creds = json!({
+            "fulldomain": "8e57.auth.acme-dns.io",
+            "password": "pw",
+            "subdomain": "8e57",
+            "username": "user-uuid"
+        });
+
+        let cases = [
+            (RegisterBody::EmptyObject, "{}", true),
+            (RegisterBody::Empty, "", true),
+            (RegisterBody::Omitted, "", false),
+        ];
+        for (mode, bytes, json_content_type) in cases {
+            let mock = server
+                .mock_async(|when, then| {
+                    let when = when.method(POST).path("/register").body(bytes);
+                    if json_content_type {
+                        when.header("content-type", "application/json");
+                    } else {
+                        when.matches(|req| {
+                            !req.headers
+                                .iter()
+                                .flatten()
+                                .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                        });
+                    }
+                    then.status(201).json_body(creds.clone());
+                })
+                .await;
+
+            let client = AcmeDnsClient::builder(server.base_url())
+                .register_body(mode)
+                .build()
+                .unwrap();
+            client.register(None).await.unwrap();
+            mock.assert_async().await;
+            mock.delete_async().await;
+        }
+    }
+
+    #[cfg(feature = "reqwest-middleware")]
+    #[tokio::test]
+    async fn middleware_client_sees_every_request() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Counter(Arc<AtomicUsize>);
+
+        #[async_trait::async_trait]
+        impl reqwest_middleware::Middleware for Counter {
+            async fn handle(
+                &self,
+                req: reqwest::Request,
+                extensions: &mut http::Extensions,
+                next: reqwest_middleware::Next<'_>,
+            ) -> reqwest_middleware::Result<Response> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                next.run(req, extensions).await
+            }
+        }
+
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .header("X-Api-User", "user-uuid");
+            then.status(200);
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let middleware = reqwest_middleware::ClientBuilder::new(HttpClient::new())
+            .with(Counter(count.clone()))
+            .build();
+        let client = AcmeDnsClient::with_middleware_client(server.base_url(), middleware).unwrap();
+
+        client.update_txt(&test_creds(), "token").await.unwrap();
+        client.health().await.unwrap();
+
+        update.assert();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn credentials_callback_sees_new_credentials() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "new-pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/allowfrom");
+            then.status(200);
+        });
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = AcmeDnsClient::builder(server.base_url())
+            .on_credentials_changed({
+                let seen = seen.clone();
+                move |creds| {
+                    seen.lock()
+                        .unwrap()
+                        .push((creds.password.clone(), creds.allowfrom.clone()))
+                }
+            })
+            .build()
+            .unwrap();
+
+        let creds = client.register(None).await.unwrap();
+        let cidrs = vec!["10.0.0.0/8".to_string()];
+        client.update_allowfrom(&creds, &cidrs).await.unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("new-pw".to_string(), vec![]),
+                ("new-pw".to_string(), cidrs),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn register_many_returns_every_account() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let accounts = client.register_many(5, None, 2).await.unwrap();
+
+        mock.assert_hits(5);
+        assert_eq!(accounts.len(), 5);
+        assert!(accounts.iter().all(|c| c.subdomain == "8e57"));
+    }
+
+    #[tokio::test]
+    async fn register_many_settled_collects_errors() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(500);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let results = client.register_many_settled(3, None, 3).await;
+        mock.assert_hits(3);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.is_err()));
+
+        let err = client.register_many(3, None, 1).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Status);
+        mock.assert_hits(4);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn register_parses_gzip_encoded_body() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(
+            br#"{"username":"user-uuid","password":"pw","fulldomain":"8e57.auth.acme-dns.io","subdomain":"8e57"}"#,
+        )
+        .unwrap();
+        let body = gzip.finish().unwrap();
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201)
+                .header("Content-Type", "application/json")
+                .header("Content-Encoding", "gzip")
+                .body(body);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register(None).await.unwrap();
+
+        assert_eq!(creds.subdomain, "8e57");
+    }
+
+    #[tokio::test]
+    async fn register_accepts_200_from_forks() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(200).json_body(json!({
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register(None).await.unwrap();
+
+        mock.assert();
+        assert_eq!(creds.subdomain, "8e57");
+    }
+
+    #[tokio::test]
+    async fn register_unexpected_status_errors() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(400).body("bad request");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client.register(None).await.unwrap_err();
+
+        mock.assert();
+
+        let Error::UnexpectedStatus { status, body, .. } = err else {
+            panic!("expected UnexpectedStatus, got {err:?}");
+        };
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "bad request");
+    }
+
+    #[tokio::test]
+    async fn update_sends_headers_and_body() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .header("X-Api-User", "user-uuid")
+                .header("X-Api-Key", "pw")
+                .json_body(json!({
+                    "subdomain": "8e57",
+                    "txt": "token123"
+                }));
+
+            then.status(200).body("OK");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = Credentials {
+            username: "user-uuid".into(),
+            password: "pw".into(),
+            subdomain: "8e57".into(),
+            fulldomain: "8e57.auth.acme-dns.io".into(),
+            allowfrom: vec![],
+        };
+
+        client.update_txt(&creds, "token123").await.unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn update_unexpected_status_errors() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(400).body("bad_txt");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = Credentials {
+            username: "user-uuid".into(),
+            password: "pw".into(),
+            subdomain: "8e57".into(),
+            fulldomain: "8e57.auth.acme-dns.io".into(),
+            allowfrom: vec![],
+        };
+
+        let err = client.update_txt(&creds, "token123").await.unwrap_err();
+
+        mock.assert();
+
+        let Error::UnexpectedStatus { status, body, .. } = err else {
+            panic!("expected UnexpectedStatus, got {err:?}");
+        };
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "bad_txt");
+    }
+
+    #[tokio::test]
+    async fn health_ok() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).body("OK");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client.health().await.unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn health_unexpected_status() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(500).body("boom");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client.health().await.unwrap_err();
+
+        mock.assert();
+
+        let Error::UnexpectedStatus { status, body, .. } = err else {
+            panic!("expected UnexpectedStatus, got {err:?}");
+        };
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, "boom");
+    }
+
+    /// Serializes tests that modify the process environment.
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn client_from_env_works() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        // `set_var` is unsafe in Rust 2024.
+        unsafe {
+            env::set_var("ACME_DNS_API_BASE", "https://example.invalid");
+        }
+
+        let client = AcmeDnsClient::from_env().unwrap();
+        // Just make sure it parses and constructs; we don't need to use it further.
+        let _ = client;
+    }
+
+    #[test]
+    fn client_from_env_missing_env_errors() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        // Remove the var to ensure the MissingEnv branch is hit.
+        unsafe {
+            env::remove_var("ACME_DNS_API_BASE");
+        }
+
+        let err = AcmeDnsClient::from_env().unwrap_err();
+
+        let Error::MissingEnv(name) = err else {
+            panic!("expected MissingEnv, got {err:?}");
+        };
+        assert_eq!(name, "ACME_DNS_API_BASE");
+    }
+
+    #[test]
+    fn credentials_from_env_works() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        unsafe {
+            env::set_var("ACME_DNS_USERNAME", "u");
+            env::set_var("ACME_DNS_PASSWORD", "p");
+            env::set_var("ACME_DNS_SUBDOMAIN", "s");
+            env::set_var("ACME_DNS_FULLDOMAIN", "s.auth.example.org");
+            env::set_var("ACME_DNS_ALLOWFROM", "1.2.3.4/32, 10.0.0.0/8");
+        }
+
+        let creds = Credentials::from_env().unwrap();
+        assert_eq!(creds.username, "u");
+        assert_eq!(creds.password, "p");
+        assert_eq!(creds.subdomain, "s");
+        assert_eq!(creds.fulldomain, "s.auth.example.org");
+        assert_eq!(
+            creds.allowfrom,
+            vec!["1.2.3.4/32".to_string(), "10.0.0.0/8".to_string()]
+        );
+    }
+
+    #[test]
+    fn to_env_exports_quotes_for_the_shell() {
+        let creds = Credentials {
+            password: r#"it's a "$(secret)" \ `x`"#.into(),
+            allowfrom: vec!["10.0.0.0/8".into(), "192.0.2.1/32".into()],
+            ..test_creds()
+        };
+
+        assert_eq!(
+            creds.to_env_exports(),
+            concat!(
+                "export ACME_DNS_USERNAME='user-uuid'\n",
+                r#"export ACME_DNS_PASSWORD='it'\''s a "$(secret)" \ `x`'"#,
+                "\n",
+                "export ACME_DNS_SUBDOMAIN='8e57'\n",
+                "export ACME_DNS_FULLDOMAIN='8e57.auth.acme-dns.io'\n",
+                "export ACME_DNS_ALLOWFROM='10.0.0.0/8,192.0.2.1/32'\n",
+            )
+        );
+    }
+
+    #[test]
+    fn merge_env_overrides_only_the_set_vars() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        unsafe {
+            for suffix in ["USERNAME", "SUBDOMAIN", "FULLDOMAIN", "ALLOWFROM"] {
+                env::remove_var(format!("ACME_DNS_{suffix}"));
+            }
+            env::set_var("ACME_DNS_PASSWORD", "from-secret");
+        }
+
+        let mut creds = test_creds();
+        creds.merge_env();
+        unsafe {
+            env::remove_var("ACME_DNS_PASSWORD");
+        }
+
+        let original = test_creds();
+        assert_eq!(creds.password, "from-secret");
+        assert_eq!(creds.username, original.username);
+        assert_eq!(creds.subdomain, original.subdomain);
+        assert_eq!(creds.fulldomain, original.fulldomain);
+        assert_eq!(creds.allowfrom, original.allowfrom);
+    }
+
+    #[test]
+    fn from_env_prefixed_reads_prefixed_vars() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        unsafe {
+            env::set_var("PRIMARY_ACME_DNS_API_BASE", "https://primary.example.org/");
+            env::set_var("PRIMARY_ACME_DNS_USERNAME", "pu");
+            env::set_var("PRIMARY_ACME_DNS_PASSWORD", "pp");
+            env::set_var("PRIMARY_ACME_DNS_SUBDOMAIN", "ps");
+            env::set_var("PRIMARY_ACME_DNS_FULLDOMAIN", "ps.auth.example.org");
+            env::set_var("PRIMARY_ACME_DNS_ALLOWFROM", "10.0.0.0/8");
+            env::set_var("ACME_DNS_USERNAME", "default-user");
+        }
+
+        let client = AcmeDnsClient::from_env_prefixed("PRIMARY_ACME_DNS").unwrap();
+        assert_eq!(client.base_url().as_str(), "https://primary.example.org/");
+
+        let creds = Credentials::from_env_prefixed("PRIMARY_ACME_DNS").unwrap();
+        assert_eq!(creds.username, "pu");
+        assert_eq!(creds.password, "pp");
+        assert_eq!(creds.subdomain, "ps");
+        assert_eq!(creds.fulldomain, "ps.auth.example.org");
+        assert_eq!(creds.allowfrom, vec!["10.0.0.0/8".to_string()]);
+
+        let err = Credentials::from_env_prefixed("SECONDARY_ACME_DNS").unwrap_err();
+        let Error::MissingEnvVars(missing) = err else {
+            panic!("expected MissingEnvVars, got {err:?}");
+        };
+        assert_eq!(missing[0], "SECONDARY_ACME_DNS_USERNAME");
+        assert_eq!(missing.len(), 4);
+
+        let err = AcmeDnsClient::from_env_prefixed("SECONDARY_ACME_DNS").unwrap_err();
+        assert!(
+            matches!(&err, Error::MissingEnvVars(missing) if missing == &["SECONDARY_ACME_DNS_API_BASE"]),
+            "got {err:?}"
+        );
+    }
+
+    #[test]
+    fn credentials_from_env_reports_every_missing_var() {
+        use std::env;
+        let _env = ENV_LOCK.lock().unwrap();
+
+        unsafe {
+            env::set_var("ACME_DNS_USERNAME", "u");
+            env::remove_var("ACME_DNS_PASSWORD");
+            env::set_var("ACME_DNS_SUBDOMAIN", "s");
+            env::remove_var("ACME_DNS_FULLDOMAIN");
+        }
+
+        assert_eq!(
+            Credentials::check_env(),
+            Err(vec!["ACME_DNS_PASSWORD", "ACME_DNS_FULLDOMAIN"])
+        );
+        let err = Credentials::from_env().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing required environment variables ACME_DNS_PASSWORD, ACME_DNS_FULLDOMAIN"
+        );
+
+        unsafe {
+            env::set_var("ACME_DNS_PASSWORD", "p");
+        }
+        let err = Credentials::from_env().unwrap_err();
+        assert!(
+            matches!(err, Error::MissingEnv("ACME_DNS_FULLDOMAIN")),
+            "got {err:?}"
+        );
+    }
+
+    #[test]
+    fn credentials_from_systemd_creds_reads_files() {
+        use std::{env, fs};
+        let _env = ENV_LOCK.lock().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, value) in [
+            ("acme_dns_api_base", "https://auth.example.org/\n"),
+            ("acme_dns_username", "u\n"),
+            ("acme_dns_password", "p\n"),
+            ("acme_dns_subdomain", "s"),
+            ("acme_dns_allowfrom", "1.2.3.4/32,10.0.0.0/8\n"),
+        ] {
+            fs::write(dir.path().join(name), value).unwrap();
+        }
+        unsafe {
+            env::set_var("CREDENTIALS_DIRECTORY", dir.path());
+        }
+
+        let err = Credentials::from_systemd_creds().unwrap_err();
+        assert!(
+            matches!(err, Error::MissingCredential("acme_dns_fulldomain")),
+            "got {err:?}"
+        );
+
+        fs::write(
+            dir.path().join("acme_dns_fulldomain"),
+            "s.auth.example.org\n",
+        )
+        .unwrap();
+        let creds = Credentials::from_systemd_creds().unwrap();
+        assert_eq!(creds.username, "u");
+        assert_eq!(creds.password, "p");
+        assert_eq!(creds.subdomain, "s");
+        assert_eq!(creds.fulldomain, "s.auth.example.org");
+        assert_eq!(creds.allowfrom, vec!["1.2.3.4/32", "10.0.0.0/8"]);
+
+        let client = AcmeDnsClient::from_systemd_creds().unwrap();
+        assert_eq!(client.base_url.as_str(), "https://auth.example.org/");
+
+        unsafe {
+            env::remove_var("CREDENTIALS_DIRECTORY");
+        }
+    }
+
+    #[test]
+    fn new_with_invalid_url_errors() {
+        let err = AcmeDnsClient::new("not a url").unwrap_err();
+
+        let Error::Url(_) = err else {
+            panic!("expected Error::Url, got {err:?}");
+        };
+    }
+
+    #[tokio::test]
+    async fn register_invalid_json_errors() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            // 201 but body is not valid JSON
+            then.status(201).body("this is not json");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client.register(None).await.unwrap_err();
+
+        mock.assert();
+
+        let Error::Json(_) = err else {
+            panic!("expected Error::Json, got {err:?}");
+        };
+    }
+
+    #[tokio::test]
+    async fn same_host_redirect_is_followed() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(307).header("Location", "/v2/update");
+        });
+        let moved = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v2/update")
+                .header("X-Api-Key", "pw");
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client.update_txt(&test_creds(), "token").await.unwrap();
+        moved.assert();
+    }
+
+    #[tokio::test]
+    async fn cross_host_redirect_is_rejected() {
+        let elsewhere = MockServer::start();
+        let leaked = elsewhere.mock(|when, then| {
+            when.any_request();
+            then.status(200);
+        });
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(307).header(
+                "Location",
+                format!("http://localhost:{}/update", elsewhere.port()),
+            );
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client.update_txt(&test_creds(), "token").await.unwrap_err();
+        assert!(
+            matches!(err, Error::UnexpectedStatus { status, .. } if status == 307),
+            "{err:?}"
+        );
+        leaked.assert_hits(0);
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .build()
+            .unwrap();
+        client.update_txt(&test_creds(), "token").await.unwrap();
+        leaked.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn record_har_captures_exchanges_without_secrets() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201)
+                .header("Content-Type", "application/json")
+                .json_body(serde_json::json!({
+                    "username": "user-uuid",
+                    "password": "hunter2-secret",
+                    "subdomain": "8e57",
+                    "fulldomain": "8e57.auth.acme-dns.io",
+                    "allowfrom": [],
+                }));
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200).body(r#"{"txt": "token"}"#);
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exchanges.har");
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .record_har(&path)
+            .build()
+            .unwrap();
+        let creds = client.register(None).await.unwrap();
+        assert_eq!(creds.password, "hunter2-secret");
+        client.update_txt(&creds, "token").await.unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("hunter2-secret"), "{raw}");
+        let har: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        let entries = har["log"]["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["response"]["status"], 201);
+        assert_eq!(entries[1]["request"]["method"], "POST");
+        assert!(
+            entries[1]["request"]["url"]
+                .as_str()
+                .unwrap()
+                .ends_with("/update")
+        );
+        let api_key = entries[1]["request"]["headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["name"] == "x-api-key")
+            .unwrap();
+        assert_eq!(api_key["value"], "***");
+        assert_eq!(
+            entries[1]["response"]["content"]["text"],
+            r#"{"txt": "token"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_response_body_errors() {
+        let server = MockServer::start();
+        let huge = "x".repeat(64 * 1024);
+
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).body(&huge);
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(500).body(&huge);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .max_response_size(1024)
+            .build()
+            .unwrap();
+        let err = client.register(None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::ResponseTooLarge { limit: 1024 }),
+            "{err:?}"
+        );
+
+        let err = client.update_txt(&test_creds(), "token").await.unwrap_err();
+        assert!(
+            matches!(err, Error::ResponseTooLarge { limit: 1024 }),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn current_txt_returns_resolved_values() {
+        let resolver = StubResolver::default().with("8e57.auth.acme-dns.io", &["token123"]);
+        let client = AcmeDnsClient::new("https://auth.example.org/")
+            .unwrap()
+            .with_resolver(resolver);
+
+        let txt = client.current_txt(&test_creds()).await.unwrap();
+        assert_eq!(txt, vec!["token123"]);
+    }
+
+    #[tokio::test]
+    async fn current_txt_uses_configured_nameserver() {
+        let addr = dns::tests::spawn_txt_server(HashMap::from([(
+            "8e57.auth.acme-dns.io".to_string(),
+            vec!["from-ns".to_string()],
+        )]))
+        .await;
+
+        let client = AcmeDnsClient::builder("https://auth.example.org/")
+            .nameserver(addr)
+            .build()
+            .unwrap();
+
+        let txt = client.current_txt(&test_creds()).await.unwrap();
+        assert_eq!(txt, vec!["from-ns"]);
+    }
+
+    #[tokio::test]
+    async fn update_txt_if_changed_skips_values_already_served() {
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .with_resolver(StubResolver::default().with("8e57.auth.acme-dns.io", &["token123"]));
+
+        let outcome = client
+            .update_txt_if_changed(&test_creds(), "token123")
+            .await
+            .unwrap();
+        assert_eq!(outcome, UpdateOutcome::AlreadySet);
+        update.assert_hits(0);
+
+        let outcome = client
+            .update_txt_if_changed(&test_creds(), "token456")
+            .await
+            .unwrap();
+        assert_eq!(outcome, UpdateOutcome::Updated);
+        update.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn compare_and_set_updates_only_on_match() {
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .json_body(serde_json::json!({"subdomain": "8e57", "txt": "token456"}));
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .with_resolver(StubResolver::default().with("8e57.auth.acme-dns.io", &["token123"]));
+
+        client
+            .update_txt_compare_and_set(&test_creds(), Some("token123"), "token456")
+            .await
+            .unwrap();
+        update.assert_hits(1);
+
+        let err = client
+            .update_txt_compare_and_set(&test_creds(), Some("other"), "token456")
+            .await
+            .unwrap_err();
+        let Error::Precondition { expected, found } = &err else {
+            panic!("expected Precondition, got {err:?}");
+        };
+        assert_eq!(expected.as_deref(), Some("other"));
+        assert_eq!(found, &vec!["token123".to_string()]);
+
+        let err = client
+            .update_txt_compare_and_set(&test_creds(), None, "token456")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Precondition { .. }), "got {err:?}");
+        update.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn compare_and_set_none_requires_absent_record() {
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .with_resolver(StubResolver::default());
+
+        client
+            .update_txt_compare_and_set(&test_creds(), None, "token456")
+            .await
+            .unwrap();
+        update.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn current_txt_nxdomain_is_empty() {
+        let client = AcmeDnsClient::new("https://auth.example.org/")
+            .unwrap()
+            .with_resolver(StubResolver::default());
+
+        let txt = client.current_txt(&test_creds()).await.unwrap();
+        assert!(txt.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deadline_cuts_slow_operations_short() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).delay(Duration::from_millis(500));
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let soon = tokio::time::Instant::now() + Duration::from_millis(50);
+
+        let err = client.register_with_deadline(None, soon).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout), "got {err:?}");
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+
+        let later = tokio::time::Instant::now() + Duration::from_secs(5);
+        client
+            .update_txt_with_deadline(&test_creds(), "token123", later)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn accessors_allow_custom_requests() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/custom")
+                .header("X-Tenant", "lab");
+            then.status(204);
+        });
+
+        let client = AcmeDnsClient::builder(server.url("/api"))
+            .default_header("X-Tenant", "lab")
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url().as_str(), server.url("/api/"));
+
+        let url = client.base_url().join("custom").unwrap();
+        let resp = client.http_client().get(url).send().await.unwrap();
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        mock.assert();
+    }
+
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[tokio::test]
+    async fn accept_invalid_certs_builds_a_working_client() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        client.health().await.unwrap();
+
+        mock.assert();
+    }
+
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[test]
+    fn malformed_root_ca_pem_is_rejected() {
+        for pem in [
+            "not a certificate",
+            "-----BEGIN CERTIFICATE-----\n!!!!\n-----END CERTIFICATE-----\n",
+        ] {
+            let err = AcmeDnsClient::builder("https://auth.example.org/")
+                .root_ca_pem(pem)
+                .build()
+                .unwrap_err();
+            assert!(matches!(err, Error::InvalidCertificate(_)), "got {err:?}");
+            assert_eq!(err.kind(), ErrorKind::Config);
+        }
+    }
+
+    // Exercising h2c needs an HTTP/2-only mock server; httpmock speaks
+    // HTTP/1.1, so this only checks the option is wired through.
+    #[cfg(feature = "http2")]
+    #[test]
+    fn http2_prior_knowledge_builds() {
+        AcmeDnsClient::builder("http://auth.example.org/")
+            .http2_prior_knowledge(true)
+            .build()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn pool_options_build_a_working_client() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .pool_max_idle_per_host(2)
+            .pool_idle_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        client.health().await.unwrap();
+        client.health().await.unwrap();
+
+        mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn slow_server_is_classified_as_timeout() {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200).delay(Duration::from_millis(500));
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let err = client.health().await.unwrap_err();
+
+        assert!(matches!(err, Error::Http(_)), "got {err:?}");
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn update_txt_with_timeout_overrides_client_timeout() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200).delay(Duration::from_millis(500));
+        });
+        let client = AcmeDnsClient::builder(server.base_url())
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let err = client
+            .update_txt_with_timeout(&test_creds(), "token", Duration::from_millis(50))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Timeout, "got {err:?}");
+        assert!(started.elapsed() < Duration::from_millis(400));
+        client.update_txt(&test_creds(), "token").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refused_connection_is_classified_as_connect() {
+        // Nothing listens on port 1.
+        let client = AcmeDnsClient::new("http://127.0.0.1:1/").unwrap();
+        let err = client.health().await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Connect);
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn non_http_errors_have_kinds() {
+        assert_eq!(Error::MissingEnv("X").kind(), ErrorKind::Config);
+        assert_eq!(
+            Error::UnexpectedStatus {
+                status: StatusCode::BAD_GATEWAY,
+                headers: HeaderMap::new(),
+                body: String::new(),
+            }
+            .kind(),
+            ErrorKind::Status
+        );
+        assert_eq!(Error::Dns("servfail".into()).kind(), ErrorKind::Dns);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retryable() {
+        let status = |code: u16| Error::UnexpectedStatus {
+            status: StatusCode::from_u16(code).unwrap(),
+            headers: HeaderMap::new(),
+            body: String::new(),
+        };
+        assert!(status(500).is_retryable());
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(400).is_retryable());
+        assert!(!status(404).is_retryable());
+
+        let json = serde_json::from_str::<Credentials>("{").unwrap_err();
+        let url = url::Url::parse("not a url").unwrap_err();
+        for err in [
+            Error::Unauthorized {
+                status: StatusCode::UNAUTHORIZED,
+                body: "no".into(),
+                request_id: None,
+            },
+            Error::InvalidCidr("10.0.0.1".into()),
+            Error::Json(json),
+            Error::Url(url),
+            Error::MissingEnv("ACME_DNS_USERNAME"),
+            Error::DryRun,
+        ] {
+            assert!(!err.is_retryable(), "{err:?}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn error_response_serializes_every_variant() {
+        let http = HttpClient::new().get("not a url").build().unwrap_err();
+        let json = serde_json::from_str::<Credentials>("{").unwrap_err();
+        let url = url::Url::parse("not a url").unwrap_err();
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let cases = [
+            (Error::Http(http), "request", None),
+            (Error::Json(json), "decode", None),
+            (Error::Url(url), "config", None),
+            (Error::Io(io), "config", None),
+            (
+                Error::UnexpectedStatus {
+                    status: StatusCode::BAD_GATEWAY,
+                    headers: HeaderMap::new(),
+                    body: "upstream".into(),
+                },
+                "status",
+                Some(502),
+            ),
+            (
+                Error::Unauthorized {
+                    status: StatusCode::UNAUTHORIZED,
+                    body: "no".into(),
+                    request_id: None,
+                },
+                "unauthorized",
+                Some(401),
+            ),
+            (
+                Error::Forbidden {
+                    status: StatusCode::FORBIDDEN,
+                    reason: "update not allowed from IP".into(),
+                    body: "{}".into(),
+                    request_id: None,
+                },
+                "unauthorized",
+                Some(403),
+            ),
+            (Error::MissingEnv("X"), "config", None),
+            (
+                Error::MissingEnvVars(vec!["X".into(), "Y".into()]),
+                "config",
+                None,
+            ),
+            (Error::MissingField("username"), "config", None),
+            (
+                Error::MissingCredential("acme_dns_password"),
+                "config",
+                None,
+            ),
+            (Error::InvalidCidr("x".into()), "config", None),
+            (Error::InvalidHeader("x".into()), "config", None),
+            (Error::InvalidCertificate("x".into()), "config", None),
+            (Error::Acme("x".into()), "request", None),
+            (Error::DryRun, "dry_run", None),
+            (Error::Timeout, "timeout", None),
+            (Error::Dns("servfail".into()), "dns", None),
+            (Error::NxDomain("x".into()), "dns", None),
+            (
+                Error::PropagationTimeout {
+                    name: "x".into(),
+                    answers: vec![],
+                },
+                "timeout",
+                None,
+            ),
+            (
+                Error::HealthTimeout {
+                    waited: std::time::Duration::from_secs(1),
+                    last: Box::new(Error::Timeout),
+                },
+                "timeout",
+                None,
+            ),
+            (
+                Error::RolledBack {
+                    cause: Box::new(Error::Timeout),
+                    restored: vec!["old".into()],
+                },
+                "timeout",
+                None,
+            ),
+            (
+                Error::RollbackFailed {
+                    cause: Box::new(Error::Timeout),
+                    rollback: Box::new(Error::Dns("servfail".into())),
+                },
+                "timeout",
+                None,
+            ),
+            (Error::Cancelled, "cancelled", None),
+        ];
+
+        for (err, kind, status) in cases {
+            let value = serde_json::to_value(ErrorResponse::from(&err)).unwrap();
+            assert_eq!(
+                value,
+                serde_json::json!({
+                    "kind": kind,
+                    "status": status,
+                    "message": err.to_string(),
+                }),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn credentials_save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.json");
+
+        test_creds().save_to_path(&path).unwrap();
+        let loaded = Credentials::load_from_path(&path).unwrap();
+
+        assert_eq!(loaded.username, "user-uuid");
+        assert_eq!(loaded.password, "pw");
+        assert_eq!(loaded.fulldomain, "8e57.auth.acme-dns.io");
+    }
+
+    #[tokio::test]
+    async fn register_if_absent_reuses_existing_file() {
+        let server = MockServer::start();
+        let register = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201);
+        });
+        let health = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.json");
+        test_creds().save_to_path(&path).unwrap();
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register_if_absent(&path, None).await.unwrap();
+
+        health.assert();
+        register.assert_hits(0);
+        assert_eq!(creds.username, "user-uuid");
+    }
+
+    #[tokio::test]
+    async fn register_if_absent_registers_and_saves() {
+        let server = MockServer::start();
+        let register = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "fulldomain": "fresh.auth.acme-dns.io",
+                "password": "new-pw",
+                "subdomain": "fresh",
+                "username": "new-user"
+            }));
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.json");
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let creds = client.register_if_absent(&path, None).await.unwrap();
+
+        register.assert();
+        assert_eq!(creds.username, "new-user");
+        let saved = Credentials::load_from_path(&path).unwrap();
+        assert_eq!(saved.password, "new-pw");
+    }
+
+    #[tokio::test]
+    async fn update_txt_for_key_auth_sends_digest() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/update").json_body(json!({
+                "subdomain": "8e57",
+                "txt": dns01_txt_value("token.thumbprint")
+            }));
+            then.status(200).body("OK");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client
+            .update_txt_for_key_auth(&test_creds(), "token.thumbprint")
+            .await
+            .unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn unexpected_status_captures_retry_after() {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(503)
+                .header("Retry-After", "30")
+                .body("slow down");
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+
+        let Error::UnexpectedStatus {
+            status, headers, ..
+        } = &err
+        else {
+            panic!("expected UnexpectedStatus, got {err:?}");
+        };
+        assert_eq!(*status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(headers.get("retry-after").unwrap(), "30");
+        // Headers are for inspection, not for the one-line message.
+        assert_eq!(
+            err.to_string(),
+            "server returned unexpected status 503 Service Unavailable: slow down"
+        );
+    }
+
+    async fn rate_limit_error(then: impl FnOnce(httpmock::Then)) -> Error {
+        let server = MockServer::start_async().await;
+        server.mock(|when, t| {
+            when.method(POST).path("/update");
+            then(t.status(429));
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn rate_limit_reads_json_retry_after() {
+        let err = rate_limit_error(|then| {
+            then.body(r#"{"error":"rate_limited","retry_after":30}"#);
+        })
+        .await;
+
+        assert!(
+            matches!(err, Error::RateLimited { retry_after: Some(d), .. } if d.as_secs() == 30),
+            "got {err:?}"
+        );
+        assert_eq!(err.to_string(), "rate limited by server, retry after 30s");
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn rate_limit_reads_retry_after_header() {
+        let err = rate_limit_error(|then| {
+            then.header("Retry-After", "7").body("slow down");
+        })
+        .await;
+
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+        let Error::RateLimited { headers, body, .. } = err else {
+            panic!("expected RateLimited, got {err:?}");
+        };
+        assert_eq!(headers["retry-after"], "7");
+        assert_eq!(body, "slow down");
+    }
+
+    #[tokio::test]
+    async fn plain_rate_limit_has_no_delay() {
+        let err = rate_limit_error(|_| {}).await;
+
+        assert!(
+            matches!(
+                err,
+                Error::RateLimited {
+                    retry_after: None,
+                    ..
+                }
+            ),
+            "got {err:?}"
+        );
+        assert_eq!(err.kind(), ErrorKind::Status);
+        assert_eq!(err.to_string(), "rate limited by server");
+    }
+
+    #[tokio::test]
+    async fn register_retries_reuse_the_idempotency_key() {
+        let server = MockServer::start_async().await;
+        let failing = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/register");
+                then.status(503);
+            })
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("register.har");
+        let client = AcmeDnsClient::builder(server.base_url())
+            .register_idempotency_key(true)
+            .retry(RetryPolicy {
+                max_retries: 1,
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            })
+            .record_har(&path)
+            .build()
+            .unwrap();
+
+        let register = client.register(None);
+        let recover = async {
+            while failing.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            failing.delete_async().await;
+            server
+                .mock_async(|when, then| {
+                    when.method(POST).path("/register");
+                    then.status(201).json_body(json!({
+                        "allowfrom": [],
+                        "fulldomain": "8e57.auth.acme-dns.io",
+                        "password": "pw",
+                        "subdomain": "8e57",
+                        "username": "user-uuid"
+                    }));
+                })
+                .await
+        };
+        let (result, _ok) = tokio::join!(register, recover);
+        result.unwrap();
+        client.register(None).await.unwrap();
+
+        let har: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let keys: Vec<_> = har["log"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                let headers = entry["request"]["headers"].as_array().unwrap();
+                let key = headers
+                    .iter()
+                    .find(|h| h["name"] == "idempotency-key")
+                    .expect("Idempotency-Key sent");
+                key["value"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0], keys[1], "retry reuses the key");
+        assert_ne!(keys[1], keys[2], "a new call gets a new key");
+        assert_eq!(keys[0].len(), 36);
+    }
+
+    #[tokio::test]
+    async fn retry_honors_json_retry_after() {
+        let server = MockServer::start_async().await;
+        let limited = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(429)
+                    .body(r#"{"error":"rate_limited","retry_after":1}"#);
+            })
+            .await;
+        let client = AcmeDnsClient::builder(server.base_url())
+            .retry(RetryPolicy {
+                max_retries: 1,
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            })
+            .build()
+            .unwrap();
+
+        let creds = test_creds();
+        let started = std::time::Instant::now();
+        let update = client.update_txt(&creds, "token123");
+        let swap = async {
+            while limited.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            limited.delete_async().await;
+            server
+                .mock_async(|when, then| {
+                    when.method(POST).path("/update");
+                    then.status(200);
+                })
+                .await
+        };
+        let (result, _ok) = tokio::join!(update, swap);
+
+        result.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn retry_honors_retry_after() {
+        let server = MockServer::start_async().await;
+
+        let limited = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(429).header("Retry-After", "2");
+            })
+            .await;
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .retry(RetryPolicy {
+                max_retries: 1,
+                // Much shorter than Retry-After, so the test tells them apart.
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            })
+            .build()
+            .unwrap();
+
+        let creds = test_creds();
+        let started = std::time::Instant::now();
+        let update = client.update_txt(&creds, "token123");
+        let swap = async {
+            while limited.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            limited.delete_async().await;
+            server
+                .mock_async(|when, then| {
+                    when.method(POST).path("/update");
+                    then.status(200).body("OK");
+                })
+                .await
+        };
+        let (result, ok) = tokio::join!(update, swap);
+
+        result.unwrap();
+        ok.assert_async().await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(1900), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_after_max_retries() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(503);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .retry(RetryPolicy {
+                max_retries: 2,
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+            })
+            .build()
+            .unwrap();
+        let err = client.health().await.unwrap_err();
+
+        mock.assert_hits(3);
+        assert_eq!(err.kind(), ErrorKind::Status);
+    }
+
+    #[tokio::test]
+    async fn client_ip_header_is_sent_on_update() {
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .header("X-Forwarded-For", "203.0.113.7");
+            then.status(200);
+        });
+        let health = server.mock(|when, then| {
+            when.method(GET).path("/health").matches(|req| {
+                !req.headers
+                    .iter()
+                    .flatten()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("x-forwarded-for"))
+            });
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .client_ip_header("X-Forwarded-For", "203.0.113.7")
+            .build()
+            .unwrap();
+        client.update_txt(&test_creds(), "token123").await.unwrap();
+        client.health().await.unwrap();
+
+        update.assert();
+        health.assert();
+
+        let err = AcmeDnsClient::builder(server.base_url())
+            .client_ip_header("bad header", "x")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidHeader(_)), "got {err:?}");
+    }
+
+    #[tokio::test]
+    async fn update_txt_bulk_sends_one_array_in_bulk_mode() {
+        let server = MockServer::start_async().await;
+        let bulk = server.mock(|when, then| {
+            when.method(POST).path("/update").json_body(json!([
+                { "subdomain": "8e57", "txt": "token1" },
+                { "subdomain": "8e57", "txt": "token2" },
+            ]));
+            then.status(200);
+        });
+        let client = AcmeDnsClient::builder(server.base_url())
+            .bulk_update(true)
+            .build()
+            .unwrap();
+
+        client
+            .update_txt_bulk(&test_creds(), &["token1", "token2"])
+            .await
+            .unwrap();
+
+        bulk.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn update_txt_bulk_falls_back_to_one_call_each() {
+        let server = MockServer::start_async().await;
+        let single = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .body_contains(r#""subdomain":"8e57""#);
+            then.status(200);
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        client
+            .update_txt_bulk(&test_creds(), &["token1", "token2"])
+            .await
+            .unwrap();
+
+        single.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn update_response_leaves_the_body_to_the_caller() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(400).body(r#"{"error": "bad_txt"}"#);
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let mut resp = client
+            .update_response(&test_creds(), "token123")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await.unwrap() {
+            body.extend_from_slice(&chunk);
+        }
+        assert_eq!(body, br#"{"error": "bad_txt"}"#);
+    }
+
+    #[tokio::test]
+    async fn allowfrom_lockout_check_refuses_to_register() {
+        let server = MockServer::start_async().await;
+        server.mock(|when, then| {
+            when.method(GET).path("/ip");
+            then.status(200).body("203.0.113.7\n");
+        });
+        let register = server.mock(|when, then| {
+            when.method(POST).path("/register");
+            then.status(201).json_body(json!({
+                "allowfrom": ["203.0.113.0/24"],
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        let client = AcmeDnsClient::builder(server.base_url())
+            .check_allowfrom_lockout(server.url("/ip"))
+            .build()
+            .unwrap();
+
+        let err = client
+            .register(Some(&["10.0.0.0/8".to_string()]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::WouldLockOut { ip, allowfrom }
+                if ip.to_string() == "203.0.113.7" && allowfrom == &["10.0.0.0/8"]),
+            "got {err:?}"
+        );
+        register.assert_hits(0);
+
+        client
+            .register(Some(&["203.0.113.0/24".to_string()]))
+            .await
+            .unwrap();
+        client.register(None).await.unwrap();
+        register.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn allowfrom_lockout_check_skips_default_headers() {
+        let server = MockServer::start_async().await;
+        let leaked = server.mock(|when, then| {
+            when.method(GET).path("/ip").header_exists("x-secret");
+            then.status(500);
+        });
+        let ip = server.mock(|when, then| {
+            when.method(GET).path("/ip");
+            then.status(200).body("203.0.113.7\n");
+        });
+        let register = server.mock(|when, then| {
+            when.method(POST)
+                .path("/register")
+                .header("x-secret", "hunter2");
+            then.status(201).json_body(json!({
+                "allowfrom": ["203.0.113.0/24"],
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        let client = AcmeDnsClient::builder(server.base_url())
+            .default_header("X-Secret", "hunter2")
+            .check_allowfrom_lockout(server.url("/ip"))
+            .build()
+            .unwrap();
+
+        client
+            .register(Some(&["203.0.113.0/24".to_string()]))
+            .await
+            .unwrap();
+        leaked.assert_hits(0);
+        ip.assert_hits(1);
+        register.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn expected_host_mismatch_sends_nothing() {
+        let server = MockServer::start_async().await;
+        let any = server.mock(|when, then| {
+            when.any_request();
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .expected_host("auth.prod.example.org")
+            .build()
+            .unwrap();
+        let err = client.update_txt(&test_creds(), "token").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::HostMismatch { expected, actual }
+                if expected == "auth.prod.example.org" && actual == "127.0.0.1"),
+            "got {err:?}"
+        );
+        assert_eq!(err.kind(), ErrorKind::Config);
+        assert!(matches!(
+            client.register(None).await,
+            Err(Error::HostMismatch { .. })
+        ));
+        any.assert_hits(0);
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .expected_host("127.0.0.1.")
+            .build()
+            .unwrap();
+        client.health().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn host_header_overrides_the_url_host() {
+        let server = MockServer::start_async().await;
+        let health = server.mock(|when, then| {
+            when.method(GET)
+                .path("/health")
+                .header("Host", "auth.example.org");
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .host_header("auth.example.org")
+            .build()
+            .unwrap();
+        client.health().await.unwrap();
+
+        health.assert();
+    }
+
+    #[tokio::test]
+    async fn resolve_connects_to_the_given_address() {
+        let server = MockServer::start_async().await;
+        let health = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        let base = format!("http://auth.example.invalid:{}/", server.port());
+        let client = AcmeDnsClient::builder(base)
+            .resolve("auth.example.invalid", *server.address())
+            .build()
+            .unwrap();
+        client.health().await.unwrap();
+
+        health.assert();
+    }
+
+    #[tokio::test]
+    async fn json_content_type_overrides_the_default() {
+        const CONTENT_TYPE: &str = "application/json; charset=utf-8";
+        let server = MockServer::start();
+        let register = server.mock(|when, then| {
+            when.method(POST)
+                .path("/register")
+                .header("Content-Type", CONTENT_TYPE);
+            then.status(201).json_body(json!({
+                "allowfrom": [],
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        let update = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .header("Content-Type", CONTENT_TYPE)
+                .matches(|req| {
+                    req.headers
+                        .iter()
+                        .flatten()
+                        .filter(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                        .count()
+                        == 1
+                });
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .json_content_type(CONTENT_TYPE)
+            .build()
+            .unwrap();
+        let creds = client.register(None).await.unwrap();
+        client.update_txt(&creds, "token123").await.unwrap();
+
+        register.assert();
+        update.assert();
+
+        let err = AcmeDnsClient::builder(server.base_url())
+            .json_content_type("bad\nvalue")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidHeader(_)), "got {err:?}");
+    }
+
+    #[tokio::test]
+    async fn register_auth_is_only_sent_on_register() {
+        let server = MockServer::start();
+        let register = server.mock(|when, then| {
+            when.method(POST)
+                .path("/register")
+                .header("X-Admin-Key", "admin-secret");
+            then.status(201).json_body(json!({
+                "fulldomain": "8e57.auth.acme-dns.io",
+                "password": "pw",
+                "subdomain": "8e57",
+                "username": "user-uuid"
+            }));
+        });
+        let update = server.mock(|when, then| {
+            when.method(POST).path("/update").matches(|req| {
+                !req.headers
+                    .iter()
+                    .flatten()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("x-admin-key"))
+            });
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .register_auth("X-Admin-Key", "admin-secret")
+            .build()
+            .unwrap();
+        let creds = client.register(None).await.unwrap();
+        client.update_txt(&creds, "token123").await.unwrap();
+
+        register.assert();
+        update.assert();
+    }
+
+    #[tokio::test]
+    async fn update_txt_with_meta_captures_request_id() {
+        let server = MockServer::start_async().await;
+        let ok = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(200).header("X-Request-Id", "req-123");
+            })
+            .await;
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let meta = client
+            .update_txt_with_meta(&test_creds(), "token123")
+            .await
+            .unwrap();
+        assert_eq!(meta.request_id.as_deref(), Some("req-123"));
+
+        ok.delete_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(400)
+                    .header("X-Request-Id", "req-456")
+                    .body("bad_txt");
+            })
+            .await;
+        let err = client
+            .update_txt_with_meta(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+        assert_eq!(err.request_id(), Some("req-456"));
+    }
+
+    #[tokio::test]
+    async fn rejections_keep_request_id() {
+        let server = MockServer::start_async().await;
+        let denied = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(401)
+                    .header("X-Request-Id", "req-401")
+                    .body(r#"{"error": "forbidden"}"#);
+            })
+            .await;
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Unauthorized { .. }), "got {err:?}");
+        assert_eq!(err.request_id(), Some("req-401"));
+
+        denied.delete_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(429).header("X-Request-Id", "req-429");
+            })
+            .await;
+        let err = client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RateLimited { .. }), "got {err:?}");
+        assert_eq!(err.request_id(), Some("req-429"));
+    }
+
+    #[tokio::test]
+    async fn set_txt_raw_sends_any_value() {
+        let server = MockServer::start();
+        let value = "service=api;region=eu-west;".repeat(40);
+
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .json_body(json!({ "subdomain": "8e57", "txt": value }));
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client.set_txt_raw(&test_creds(), &value).await.unwrap();
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn custom_backoff_decides_when_to_stop() {
+        /// Retries once, then gives up.
+        #[derive(Debug, Clone)]
+        struct Once;
+        impl Backoff for Once {
+            fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+                (attempt == 0).then_some(Duration::from_millis(1))
+            }
+        }
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(503);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .backoff(Once)
+            .build()
+            .unwrap();
+        client.health().await.unwrap_err();
+
+        mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(400).body("bad_txt");
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .retry(RetryPolicy::new(3))
+            .build()
+            .unwrap();
+        client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+
+        mock.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn dry_run_sends_nothing() {
+        let server = MockServer::start();
+
+        let any = server.mock(|when, then| {
+            when.any_request();
+            then.status(500);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .dry_run(true)
+            .build()
+            .unwrap();
+
+        client.update_txt(&test_creds(), "token123").await.unwrap();
+        client.health().await.unwrap();
+        let err = client.register(None).await.unwrap_err();
+
+        assert!(matches!(err, Error::DryRun), "got {err:?}");
+        any.assert_hits(0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_allowfrom_change_is_not_reported() {
+        let server = MockServer::start();
+        let any = server.mock(|when, then| {
+            when.any_request();
+            then.status(500);
+        });
+
+        let seen = Arc::new(std::sync::Mutex::new(0));
+        let client = AcmeDnsClient::builder(server.base_url())
+            .dry_run(true)
+            .on_credentials_changed({
+                let seen = seen.clone();
+                move |_| *seen.lock().unwrap() += 1
+            })
+            .build()
+            .unwrap();
+
+        let cidrs = vec!["10.0.0.0/8".to_string()];
+        let creds = client
+            .update_allowfrom(&test_creds(), &cidrs)
+            .await
+            .unwrap();
+        assert_eq!(creds.allowfrom, cidrs);
+        assert_eq!(*seen.lock().unwrap(), 0);
+        any.assert_hits(0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_get_stored_txt_is_dry_run_error() {
+        let server = MockServer::start();
+        let any = server.mock(|when, then| {
+            when.any_request();
+            then.status(500);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .dry_run(true)
+            .build()
+            .unwrap();
+        let err = client.get_stored_txt(&test_creds()).await.unwrap_err();
+
+        assert!(matches!(err, Error::DryRun), "got {err:?}");
+        any.assert_hits(0);
+    }
+
+    #[test]
+    fn validate_cidr_accepts_networks_only() {
+        validate_cidr("192.168.100.0/24").unwrap();
+        validate_cidr("2001:db8::/32").unwrap();
+
+        let err = validate_cidr("192.168.100.1").unwrap_err();
+        let Error::InvalidCidr(cidr) = err else {
+            panic!("expected InvalidCidr, got {err:?}");
+        };
+        assert_eq!(cidr, "192.168.100.1");
+
+        for bad in [
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/+8",
+            "10.0.0.0/",
+            "/8",
+        ] {
+            assert!(validate_cidr(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn cidr_contains_masks_prefix_bits() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(cidr_contains("0.0.0.0/0", ip("203.0.113.9")));
+        assert!(cidr_contains("10.1.2.3/8", ip("10.200.0.1")));
+        assert!(cidr_contains("10.0.0.1/32", ip("10.0.0.1")));
+        assert!(!cidr_contains("10.0.0.1/32", ip("10.0.0.2")));
+        assert!(cidr_contains("::/0", ip("2001:db8::1")));
+        assert!(!cidr_contains("0.0.0.0/0", ip("::ffff:10.0.0.1")));
+        assert!(!cidr_contains("not a cidr", ip("10.0.0.1")));
+    }
+
+    #[tokio::test]
+    async fn require_uuid_subdomain_rejects_fulldomain() {
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+        let client = AcmeDnsClient::builder(server.base_url())
+            .require_uuid_subdomain(true)
+            .build()
+            .unwrap();
+
+        let creds = Credentials {
+            subdomain: "8e5700ea-a4bf-41c7-8a77-e990661dcc6a".into(),
+            ..test_creds()
+        };
+        client.update_txt(&creds, "token").await.unwrap();
+
+        let mistaken = Credentials {
+            subdomain: "8e5700ea-a4bf-41c7-8a77-e990661dcc6a.auth.acme-dns.io".into(),
+            ..test_creds()
+        };
+        let err = client.update_txt(&mistaken, "token").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidSubdomain(_)), "{err:?}");
+        assert_eq!(err.kind(), ErrorKind::Config);
+        update.assert_hits(1);
+
+        // Off by default, for forks with other subdomain formats.
+        AcmeDnsClient::new(server.base_url())
+            .unwrap()
+            .update_txt(&mistaken, "token")
+            .await
+            .unwrap();
+    }
 
+    #[tokio::test]
+    async fn register_rejects_invalid_cidr_without_request() {
+        let server = MockServer::start();
         let mock = server.mock(|when, then| {
-            when.method(GET).path("/health");
-            then.status(200).body("OK");
+            when.method(POST).path("/register");
+            then.status(201);
         });
 
         let client = AcmeDnsClient::new(server.base_url()).unwrap();
-        client.health().await.unwrap();
-        mock.assert();
+        let err = client
+            .register(Some(&["not-a-cidr".to_string()]))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::InvalidCidr(_)), "got {err:?}");
+        mock.assert_hits(0);
     }
 
     #[tokio::test]
-    async fn health_unexpected_status() {
+    async fn update_allowfrom_sends_cidrs() {
         let server = MockServer::start();
 
         let mock = server.mock(|when, then| {
-            when.method(GET).path("/health");
-            then.status(500).body("boom");
+            when.method(POST)
+                .path("/allowfrom")
+                .header("X-Api-User", "user-uuid")
+                .header("X-Api-Key", "pw")
+                .json_body(json!({ "allowfrom": ["10.0.0.0/8"] }));
+            then.status(200);
         });
 
         let client = AcmeDnsClient::new(server.base_url()).unwrap();
-        let err = client.health().await.unwrap_err();
+        let creds = client
+            .update_allowfrom(&test_creds(), &["10.0.0.0/8".to_string()])
+            .await
+            .unwrap();
 
         mock.assert();
+        assert_eq!(creds.allowfrom, vec!["10.0.0.0/8"]);
+        assert_eq!(creds.username, "user-uuid");
+    }
 
-        let Error::UnexpectedStatus { status, body } = err else {
-            panic!("expected UnexpectedStatus, got {err:?}");
-        };
+    #[tokio::test]
+    async fn get_stored_txt_reads_the_server_values() {
+        let server = MockServer::start_async().await;
+        let mut stored = server.mock(|when, then| {
+            when.method(GET)
+                .path("/txt")
+                .header("X-Api-User", "user-uuid")
+                .header("X-Api-Key", "pw");
+            then.status(200)
+                .json_body(json!({ "txt": ["token1", "token2"] }));
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
 
-        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
-        assert_eq!(body, "boom");
+        let txt = client.get_stored_txt(&test_creds()).await.unwrap();
+        assert_eq!(txt, ["token1", "token2"]);
+
+        stored.delete();
+        server.mock(|when, then| {
+            when.method(GET).path("/txt");
+            then.status(404).body("404 page not found");
+        });
+        let err = client.get_stored_txt(&test_creds()).await.unwrap_err();
+        assert!(
+            matches!(err, Error::UnexpectedStatus { status, .. } if status == 404),
+            "got {err:?}"
+        );
     }
 
     #[test]
-    fn client_from_env_works() {
-        use std::env;
+    fn credentials_builder_builds_complete_credentials() {
+        let creds = Credentials::builder()
+            .username("user-uuid")
+            .password("pw")
+            .subdomain("8e57")
+            .fulldomain("8e57.auth.acme-dns.io")
+            .allowfrom(["10.0.0.0/8"])
+            .build()
+            .unwrap();
 
-        // `set_var` is unsafe in Rust 2024.
-        unsafe {
-            env::set_var("ACME_DNS_API_BASE", "https://example.invalid");
-        }
+        assert_eq!(creds.username, "user-uuid");
+        assert_eq!(creds.password, "pw");
+        assert_eq!(creds.subdomain, "8e57");
+        assert_eq!(creds.fulldomain, "8e57.auth.acme-dns.io");
+        assert_eq!(creds.allowfrom, vec!["10.0.0.0/8"]);
+    }
 
-        let client = AcmeDnsClient::from_env().unwrap();
-        // Just make sure it parses and constructs; we don't need to use it further.
-        let _ = client;
+    #[test]
+    fn credentials_builder_reports_missing_field() {
+        let err = Credentials::builder()
+            .username("user-uuid")
+            .password("pw")
+            .fulldomain("8e57.auth.acme-dns.io")
+            .build()
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::MissingField("subdomain")),
+            "got {err:?}"
+        );
+        assert_eq!(err.kind(), ErrorKind::Config);
     }
 
     #[test]
-    fn client_from_env_missing_env_errors() {
-        use std::env;
+    fn zone_strips_the_subdomain() {
+        assert_eq!(test_creds().zone(), Some("auth.acme-dns.io"));
 
-        // Remove the var to ensure the MissingEnv branch is hit.
-        unsafe {
-            env::remove_var("ACME_DNS_API_BASE");
-        }
+        let mismatched = Credentials {
+            subdomain: "ffff".into(),
+            ..test_creds()
+        };
+        assert_eq!(mismatched.zone(), None);
 
-        let err = AcmeDnsClient::from_env().unwrap_err();
+        let prefix_only = Credentials {
+            fulldomain: "8e57abc.auth.acme-dns.io".into(),
+            ..test_creds()
+        };
+        assert_eq!(prefix_only.zone(), None);
+    }
 
-        let Error::MissingEnv(name) = err else {
-            panic!("expected MissingEnv, got {err:?}");
+    #[test]
+    fn allows_ip_checks_allowfrom_ranges() {
+        let creds = Credentials {
+            allowfrom: vec!["192.168.100.0/24".into(), "2001:db8::/32".into()],
+            ..test_creds()
         };
-        assert_eq!(name, "ACME_DNS_API_BASE");
+        #[cfg(feature = "ipnet")]
+        assert_eq!(creds.allowfrom_nets().unwrap().len(), 2);
+        assert!(creds.allows_ip("192.168.100.7".parse().unwrap()));
+        assert!(creds.allows_ip("2001:db8::1".parse().unwrap()));
+        assert!(!creds.allows_ip("192.168.101.7".parse().unwrap()));
+        assert!(!creds.allows_ip("2001:db9::1".parse().unwrap()));
     }
 
     #[test]
-    fn credentials_from_env_works() {
-        use std::env;
+    fn empty_allowfrom_allows_everything() {
+        let creds = Credentials {
+            allowfrom: vec![],
+            ..test_creds()
+        };
+        #[cfg(feature = "ipnet")]
+        assert!(creds.allowfrom_nets().unwrap().is_empty());
+        assert!(creds.allows_ip("203.0.113.9".parse().unwrap()));
+    }
 
-        unsafe {
-            env::set_var("ACME_DNS_USERNAME", "u");
-            env::set_var("ACME_DNS_PASSWORD", "p");
-            env::set_var("ACME_DNS_SUBDOMAIN", "s");
-            env::set_var("ACME_DNS_FULLDOMAIN", "s.auth.example.org");
-            env::set_var("ACME_DNS_ALLOWFROM", "1.2.3.4/32, 10.0.0.0/8");
-        }
+    #[cfg(feature = "ipnet")]
+    #[test]
+    fn allowfrom_nets_rejects_bad_cidrs() {
+        let creds = Credentials {
+            allowfrom: vec!["10.0.0.1".into()],
+            ..test_creds()
+        };
+        let err = creds.allowfrom_nets().unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidCidr(ref c) if c == "10.0.0.1"),
+            "{err:?}"
+        );
+        assert!(!creds.allows_ip("10.0.0.1".parse().unwrap()));
+    }
 
-        let creds = Credentials::from_env().unwrap();
+    #[test]
+    fn cname_instructions_point_at_fulldomain() {
+        let creds = test_creds();
+        assert_eq!(
+            creds.cname_instructions("www.example.com"),
+            "_acme-challenge.www.example.com. CNAME 8e57.auth.acme-dns.io."
+        );
+        assert_eq!(
+            creds.cname_instructions("www.example.com."),
+            creds.cname_instructions("www.example.com")
+        );
+    }
+
+    #[test]
+    fn credentials_debug_hides_password() {
+        let creds = Credentials {
+            password: "s3cret-pw".into(),
+            ..test_creds()
+        };
+
+        let debug = format!("{creds:?}");
+        assert!(!debug.contains("s3cret-pw"), "{debug}");
+        assert!(debug.contains("password: \"***\""), "{debug}");
+        assert!(debug.contains("user-uuid"), "{debug}");
+
+        let shown = creds.redacted().to_string();
+        assert!(!shown.contains("s3cret-pw"), "{shown}");
+        assert!(
+            shown.contains("fulldomain=8e57.auth.acme-dns.io"),
+            "{shown}"
+        );
+
+        // Persistence still writes the real password.
+        let json = serde_json::to_string(&creds).unwrap();
+        assert!(json.contains("s3cret-pw"));
+    }
+
+    #[test]
+    fn credentials_from_json_str_and_slice() {
+        let json = r#"{
+            "username": "u",
+            "password": "p",
+            "subdomain": "s",
+            "fulldomain": "s.auth.example.org"
+        }"#;
+
+        let creds = Credentials::from_json_str(json).unwrap();
         assert_eq!(creds.username, "u");
-        assert_eq!(creds.password, "p");
+        assert!(creds.allowfrom.is_empty());
+
+        let creds = Credentials::from_json_slice(json.as_bytes()).unwrap();
+        assert_eq!(creds.fulldomain, "s.auth.example.org");
+    }
+
+    #[test]
+    fn credentials_accept_fork_field_spellings() {
+        let json = r#"{
+            "username": "u",
+            "password": "p",
+            "sub_domain": "s",
+            "full_domain": "s.auth.example.org",
+            "allowFrom": ["192.168.100.0/24"]
+        }"#;
+
+        let creds = Credentials::from_json_str(json).unwrap();
         assert_eq!(creds.subdomain, "s");
         assert_eq!(creds.fulldomain, "s.auth.example.org");
+        assert_eq!(creds.allowfrom, vec!["192.168.100.0/24"]);
+
+        let out = serde_json::to_value(&creds).unwrap();
+        assert_eq!(out["fulldomain"], "s.auth.example.org");
+        assert!(out.get("full_domain").is_none());
+    }
+
+    #[test]
+    fn credentials_from_malformed_json_errors() {
+        let err = Credentials::from_json_str(r#"{"username": "u""#).unwrap_err();
+        assert!(matches!(err, Error::Json(_)), "got {err:?}");
+
+        // Well-formed JSON missing required fields is rejected too.
+        let err = Credentials::from_json_slice(br#"{"username": "u"}"#).unwrap_err();
+        assert!(matches!(err, Error::Json(_)), "got {err:?}");
+    }
+
+    #[tokio::test]
+    async fn base_url_with_or_without_trailing_slash() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(200);
+        });
+
+        for base in [server.base_url(), format!("{}/", server.base_url())] {
+            let client = AcmeDnsClient::new(&base).unwrap();
+            client.health().await.unwrap();
+        }
+
+        mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn base_url_path_prefix_is_kept() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/api/health");
+            then.status(200);
+        });
+
+        for base in [
+            format!("{}/api", server.base_url()),
+            format!("{}/api/", server.base_url()),
+        ] {
+            let client = AcmeDnsClient::new(&base).unwrap();
+            client.health().await.unwrap();
+        }
+
+        mock.assert_hits(2);
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingMetrics {
+        calls: Mutex<Vec<(String, Option<StatusCode>)>>,
+    }
+
+    impl MetricsObserver for RecordingMetrics {
+        fn on_request(&self, endpoint: &str, status: Option<StatusCode>, _elapsed: Duration) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((endpoint.to_string(), status));
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_observer_sees_each_round_trip() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(200);
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(500);
+        });
+
+        let metrics = Arc::new(RecordingMetrics::default());
+        let client = AcmeDnsClient::builder(server.base_url())
+            .metrics(metrics.clone())
+            .build()
+            .unwrap();
+
+        client.update_txt(&test_creds(), "token123").await.unwrap();
+        client.health().await.unwrap_err();
+
+        let calls = metrics.calls.lock().unwrap().clone();
         assert_eq!(
-            creds.allowfrom,
-            vec!["1.2.3.4/32".to_string(), "10.0.0.0/8".to_string()]
+            calls,
+            vec![
+                ("update".to_string(), Some(StatusCode::OK)),
+                (
+                    "health".to_string(),
+                    Some(StatusCode::INTERNAL_SERVER_ERROR)
+                ),
+            ]
         );
     }
 
+    #[tokio::test]
+    async fn default_headers_are_sent_on_every_request() {
+        let server = MockServer::start();
+        let mocks = [
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path("/register")
+                    .header("CF-Access-Client-Id", "id")
+                    .header("CF-Access-Client-Secret", "secret");
+                then.status(201).json_body(json!({
+                    "fulldomain": "8e57.auth.acme-dns.io",
+                    "password": "pw",
+                    "subdomain": "8e57",
+                    "username": "user-uuid"
+                }));
+            }),
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path("/update")
+                    .header("CF-Access-Client-Id", "id")
+                    .header("CF-Access-Client-Secret", "secret")
+                    .header("X-Api-User", "user-uuid")
+                    .header("X-Api-Key", "pw");
+                then.status(200);
+            }),
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path("/health")
+                    .header("CF-Access-Client-Id", "id")
+                    .header("CF-Access-Client-Secret", "secret");
+                then.status(200);
+            }),
+        ];
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .default_header("CF-Access-Client-Id", "id")
+            .default_header("CF-Access-Client-Secret", "secret")
+            .build()
+            .unwrap();
+
+        let creds = client.register(None).await.unwrap();
+        client.update_txt(&creds, "token123").await.unwrap();
+        client.health().await.unwrap();
+
+        for mock in mocks {
+            mock.assert();
+        }
+    }
+
     #[test]
-    fn new_with_invalid_url_errors() {
-        let err = AcmeDnsClient::new("not a url").unwrap_err();
+    fn builder_debug_hides_default_header_values() {
+        let builder = AcmeDnsClient::builder("https://auth.example.org/")
+            .default_header("CF-Access-Client-Secret", "cf-secret-value")
+            .register_auth("X-Register-Token", "register-secret-value");
 
-        let Error::Url(_) = err else {
-            panic!("expected Error::Url, got {err:?}");
+        let debug = format!("{builder:?}");
+        assert!(debug.contains("CF-Access-Client-Secret"), "{debug}");
+        assert!(!debug.contains("cf-secret-value"), "{debug}");
+        assert!(debug.contains("X-Register-Token"), "{debug}");
+        assert!(!debug.contains("register-secret-value"), "{debug}");
+    }
+
+    #[test]
+    fn invalid_default_header_errors() {
+        let err = AcmeDnsClient::builder("https://auth.example.org/")
+            .default_header("bad header", "x")
+            .build()
+            .unwrap_err();
+
+        let Error::InvalidHeader(name) = err else {
+            panic!("expected InvalidHeader, got {err:?}");
         };
+        assert_eq!(name, "bad header");
     }
 
     #[tokio::test]
-    async fn register_invalid_json_errors() {
+    async fn prime_sets_a_digest_shaped_placeholder() {
         let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .json_body(json!({ "subdomain": "8e57", "txt": PRIME_TXT }));
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client.prime(&test_creds()).await.unwrap();
+
+        mock.assert();
+        assert_eq!(PRIME_TXT.len(), 43);
+        assert!(
+            PRIME_TXT
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        );
+    }
 
+    #[tokio::test]
+    async fn verify_credentials_accepts_valid_creds() {
+        let server = MockServer::start();
         let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/update")
+                .header("X-Api-User", "user-uuid")
+                .json_body(json!({ "subdomain": "8e57", "txt": VERIFY_TXT }));
+            then.status(200);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        client.verify_credentials(&test_creds()).await.unwrap();
+
+        mock.assert();
+        assert_eq!(VERIFY_TXT.len(), 43);
+    }
+
+    #[tokio::test]
+    async fn verify_credentials_rejects_bad_creds() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(401).body(r#"{"error": "forbidden"}"#);
+        });
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let err = client.verify_credentials(&test_creds()).await.unwrap_err();
+
+        let Error::Unauthorized { status, body, .. } = err else {
+            panic!("expected Unauthorized, got {err:?}");
+        };
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, r#"{"error": "forbidden"}"#);
+    }
+
+    #[tokio::test]
+    async fn allowfrom_rejection_is_forbidden_not_unauthorized() {
+        let server = MockServer::start();
+        let mut bad_ip = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(401)
+                .body(r#"{"error": "update not allowed from IP"}"#);
+        });
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
+        let err = client.update_txt(&test_creds(), "token").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Forbidden { status, reason, .. }
+                if *status == StatusCode::UNAUTHORIZED && reason == "update not allowed from IP"),
+            "got {err:?}"
+        );
+        assert_eq!(err.kind(), ErrorKind::Unauthorized);
+
+        bad_ip.delete();
+        server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(403).body(r#"{"error": "invalid api key"}"#);
+        });
+        let err = client.update_txt(&test_creds(), "token").await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::Unauthorized {
+                    status: StatusCode::FORBIDDEN,
+                    ..
+                }
+            ),
+            "got {err:?}"
+        );
+
+        assert!(matches!(
+            error::rejection(
+                StatusCode::UNAUTHORIZED,
+                r#"{"error": "Update Not Allowed From this address"}"#.into(),
+                None
+            ),
+            Error::Forbidden { .. }
+        ));
+        assert!(matches!(
+            error::rejection(
+                StatusCode::UNAUTHORIZED,
+                r#"{"error": "invalid ip header"}"#.into(),
+                None
+            ),
+            Error::Unauthorized { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn unauthorized_on_every_endpoint() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
             when.method(POST).path("/register");
-            // 201 but body is not valid JSON
-            then.status(201).body("this is not json");
+            then.status(403).body("registration closed");
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(401).body("login required");
         });
 
         let client = AcmeDnsClient::new(server.base_url()).unwrap();
+
         let err = client.register(None).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Unauthorized { status, body, .. }
+                if *status == StatusCode::FORBIDDEN && body == "registration closed"),
+            "got {err:?}"
+        );
+
+        let err = client.health().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Unauthorized { status, body, .. }
+                if *status == StatusCode::UNAUTHORIZED && body == "login required"),
+            "got {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn unauthorized_is_never_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/update");
+            then.status(401);
+        });
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .retry(RetryPolicy::new(3))
+            .build()
+            .unwrap();
+        let err = client
+            .update_txt(&test_creds(), "token123")
+            .await
+            .unwrap_err();
+
+        mock.assert_hits(1);
+        assert_eq!(err.kind(), ErrorKind::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn with_client_uses_the_given_http_client() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/health").header("X-Shared", "yes");
+            then.status(200);
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Shared", "yes".parse().unwrap());
+        let http = HttpClient::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
 
+        let client = AcmeDnsClient::with_client(server.base_url(), http).unwrap();
+        client.health().await.unwrap();
         mock.assert();
+    }
 
-        let Error::Json(_) = err else {
-            panic!("expected Error::Json, got {err:?}");
-        };
+    #[tokio::test]
+    async fn clone_with_base_url_shares_http_client() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+        let mocks = [&first, &second].map(|server| {
+            server.mock(|when, then| {
+                when.method(GET).path("/health").header("X-Shared", "yes");
+                then.status(200);
+            })
+        });
+
+        let client = AcmeDnsClient::builder(first.base_url())
+            .default_header("X-Shared", "yes")
+            .build()
+            .unwrap();
+        // The header lives in the reqwest client, so seeing it on the second
+        // server shows the clone reuses that client.
+        let other = client.clone_with_base_url(second.base_url()).unwrap();
+
+        client.health().await.unwrap();
+        other.health().await.unwrap();
+        for mock in mocks {
+            mock.assert();
+        }
+        assert_eq!(other.base_url.as_str(), format!("{}/", second.base_url()));
     }
 }
@@ -0,0 +1,182 @@
+//! Observability hook for HTTP round-trips.
+
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// Receives one callback per HTTP round-trip the client makes.
+///
+/// Implement this to bridge into Prometheus, StatsD, or whatever metrics
+/// system you use, then install it with
+/// [`AcmeDnsClientBuilder::metrics`](crate::AcmeDnsClientBuilder::metrics).
+/// Retries are reported individually. Without an observer nothing is recorded.
+pub trait MetricsObserver: fmt::Debug + Send + Sync {
+    /// Called after each request completes.
+    ///
+    /// `endpoint` is the API path without slashes (`"register"`, `"update"`,
+    /// `"health"`, ...). `status` is `None` when no response arrived (timeout,
+    /// connection failure). `elapsed` runs until the response headers were
+    /// received.
+    fn on_request(&self, endpoint: &str, status: Option<StatusCode>, elapsed: Duration);
+}
+
+/// A [`MetricsObserver`] that records into a Prometheus registry.
+///
+/// Registers two metrics, both labelled with `endpoint` and `result`:
+///
+/// * `acme_dns_client_requests_total` - a counter of round-trips.
+/// * `acme_dns_client_request_duration_seconds` - a histogram of their
+///   latency.
+///
+/// `result` is the HTTP status code (`"200"`, `"401"`, ...), or `"error"`
+/// when no response arrived.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::sync::Arc;
+/// use acme_dns_client::{AcmeDnsClient, PrometheusObserver};
+///
+/// let registry = prometheus::Registry::new();
+/// let client = AcmeDnsClient::builder("https://auth.acme-dns.io")
+///     .metrics(Arc::new(PrometheusObserver::new(&registry)?))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "prometheus")]
+#[derive(Debug, Clone)]
+pub struct PrometheusObserver {
+    requests: prometheus::IntCounterVec,
+    duration: prometheus::HistogramVec,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusObserver {
+    const NAMESPACE: &'static str = "acme_dns_client";
+    const LABELS: &'static [&'static str] = &["endpoint", "result"];
+
+    /// Create the metrics and register them into `registry`.
+    ///
+    /// Fails if `registry` already holds metrics with the same names.
+    pub fn new(registry: &prometheus::Registry) -> Result<Self, prometheus::Error> {
+        let requests = prometheus::IntCounterVec::new(
+            prometheus::Opts::new("requests_total", "acme-dns API requests.")
+                .namespace(Self::NAMESPACE),
+            Self::LABELS,
+        )?;
+        let duration = prometheus::HistogramVec::new(
+            prometheus::HistogramOpts::new(
+                "request_duration_seconds",
+                "acme-dns API request latency in seconds.",
+            )
+            .namespace(Self::NAMESPACE),
+            Self::LABELS,
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(duration.clone()))?;
+        Ok(Self { requests, duration })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricsObserver for PrometheusObserver {
+    fn on_request(&self, endpoint: &str, status: Option<StatusCode>, elapsed: Duration) {
+        let result = status.map_or_else(|| "error".to_string(), |s| s.as_u16().to_string());
+        let labels = [endpoint, result.as_str()];
+        self.requests.with_label_values(&labels).inc();
+        self.duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use std::sync::Arc;
+
+    use httpmock::prelude::*;
+
+    use super::*;
+    use crate::AcmeDnsClient;
+    use crate::tests::test_creds;
+
+    /// The `(endpoint, result)` label pairs of `family`, with each sample's
+    /// count.
+    fn samples(family: &prometheus::proto::MetricFamily) -> Vec<(String, String, u64)> {
+        family
+            .get_metric()
+            .iter()
+            .map(|m| {
+                let label = |name: &str| {
+                    m.get_label()
+                        .iter()
+                        .find(|l| l.name() == name)
+                        .map(|l| l.value().to_string())
+                        .unwrap_or_default()
+                };
+                let count = if family.name().ends_with("_total") {
+                    m.get_counter().get_value() as u64
+                } else {
+                    m.get_histogram().get_sample_count()
+                };
+                (label("endpoint"), label("result"), count)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn prometheus_observer_records_labelled_metrics() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/update");
+                then.status(200).json_body(serde_json::json!({"txt": "x"}));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(500);
+            })
+            .await;
+
+        let registry = prometheus::Registry::new();
+        let client = AcmeDnsClient::builder(server.base_url())
+            .metrics(Arc::new(PrometheusObserver::new(&registry).unwrap()))
+            .build()
+            .unwrap();
+        let creds = test_creds();
+        client.update_txt(&creds, "token-1").await.unwrap();
+        client.update_txt(&creds, "token-2").await.unwrap();
+        let _ = client.health().await;
+
+        let families = registry.gather();
+        let find = |name: &str| {
+            families
+                .iter()
+                .find(|f| f.name() == name)
+                .unwrap_or_else(|| panic!("{name} not registered"))
+        };
+        let mut expected = vec![
+            ("health".to_string(), "500".to_string(), 1),
+            ("update".to_string(), "200".to_string(), 2),
+        ];
+        expected.sort();
+        for name in [
+            "acme_dns_client_requests_total",
+            "acme_dns_client_request_duration_seconds",
+        ] {
+            let mut got = samples(find(name));
+            got.sort();
+            assert_eq!(got, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn prometheus_observer_rejects_double_registration() {
+        let registry = prometheus::Registry::new();
+        PrometheusObserver::new(&registry).unwrap();
+        assert!(PrometheusObserver::new(&registry).is_err());
+    }
+}
@@ -0,0 +1,1160 @@
+//! Waiting for a TXT update to become visible in DNS.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::{self, Either};
+use web_time::Instant;
+
+use crate::error::ServerAnswer;
+use crate::{AcmeDnsClient, Credentials, DnsResolver, Error, IpFamily, TxtWithTtl, timer};
+
+/// Where [`AcmeDnsClient::wait_for_propagation`] looks for the TXT value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PropagationMode {
+    /// Ask the client's configured resolver.
+    #[default]
+    Resolver,
+    /// Look up the NS records of the acme-dns zone, then ask each of those
+    /// nameservers directly. Succeeds only once all of them serve the value,
+    /// since the CA may query any of them.
+    AllNameservers,
+    /// Ask each of a set of public resolvers (by default Cloudflare, Google
+    /// and Quad9; see [`PropagationOptions::public_resolvers`]), a rough
+    /// stand-in for the CA validating from several vantage points.
+    PublicResolvers,
+}
+
+/// Resolvers [`PropagationMode::PublicResolvers`] asks by default.
+const PUBLIC_RESOLVERS: [&str; 3] = ["1.1.1.1", "8.8.8.8", "9.9.9.9"];
+
+/// The same resolvers' IPv6 addresses, asked by default with
+/// [`IpFamily::V6`].
+const PUBLIC_RESOLVERS_V6: [&str; 3] = [
+    "2606:4700:4700::1111",
+    "2001:4860:4860::8888",
+    "2620:fe::fe",
+];
+
+/// Options for [`AcmeDnsClient::wait_for_propagation`].
+///
+/// Defaults to polling the client's resolver every 5 seconds for up to 2
+/// minutes, until every server asked serves the value.
+#[derive(Debug, Clone)]
+pub struct PropagationOptions {
+    timeout: Duration,
+    interval: Duration,
+    mode: PropagationMode,
+    public_resolvers: Option<Vec<String>>,
+    quorum: Option<usize>,
+    ttl_interval: Option<(Duration, Duration)>,
+    backoff: Option<Duration>,
+    max_attempts: Option<u32>,
+    require_dnssec: bool,
+    ip_family: IpFamily,
+    on_progress: Option<ProgressCallback>,
+}
+
+/// Added to a record's TTL by [`PropagationOptions::ttl_interval`], so the
+/// next poll lands just after cached answers expire.
+const TTL_MARGIN: Duration = Duration::from_secs(1);
+
+/// Callback set with [`PropagationOptions::on_progress`].
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(&PropagationProgress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback(..)")
+    }
+}
+
+/// Where a propagation wait stands after one poll; passed to the
+/// [`PropagationOptions::on_progress`] callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropagationProgress {
+    /// Poll number, starting at 1.
+    pub attempt: u32,
+    /// Time since the wait started.
+    pub elapsed: Duration,
+    /// Servers that served the expected value on this poll.
+    pub served: Vec<String>,
+    /// Servers that did not (yet), including ones that failed to answer.
+    pub pending: Vec<String>,
+    /// How long the wait sleeps before polling again, if it does; see
+    /// [`PropagationOptions::ttl_interval`] and
+    /// [`PropagationOptions::backoff`].
+    pub next_interval: Duration,
+}
+
+impl Default for PropagationOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            interval: Duration::from_secs(5),
+            mode: PropagationMode::Resolver,
+            public_resolvers: None,
+            quorum: None,
+            ttl_interval: None,
+            backoff: None,
+            max_attempts: None,
+            require_dnssec: false,
+            ip_family: IpFamily::Any,
+            on_progress: None,
+        }
+    }
+}
+
+impl PropagationOptions {
+    /// Default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up after `timeout` with [`Error::PropagationTimeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait `interval` between polls.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Where to look for the value; see [`PropagationMode`].
+    pub fn mode(mut self, mode: PropagationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Resolvers to ask in [`PropagationMode::PublicResolvers`], as IP
+    /// addresses or host names; queries go to port 53. With an
+    /// [`ip_family`](Self::ip_family) other than [`IpFamily::Any`], IP
+    /// addresses given here must be of that family.
+    pub fn public_resolvers<S: Into<String>>(
+        mut self,
+        resolvers: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.public_resolvers = Some(resolvers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Succeed once at least `quorum` of the servers asked serve the value,
+    /// instead of all of them. Values above the number of servers mean all;
+    /// `0` is treated as `1`, so at least one server must serve it.
+    pub fn quorum(mut self, quorum: usize) -> Self {
+        self.quorum = Some(quorum.max(1));
+        self
+    }
+
+    /// Time polls by the TXT record's TTL instead of the fixed
+    /// [`interval`](Self::interval): wait roughly TTL + 1 second, clamped
+    /// to `min..=max`, so cached answers have expired by the next poll.
+    /// Uses the lowest TTL any server reported; when none reports one
+    /// (e.g. the record does not exist yet), the fixed interval applies.
+    pub fn ttl_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.ttl_interval = Some((min, max.max(min)));
+        self
+    }
+
+    /// Double the wait after each poll, starting at
+    /// [`interval`](Self::interval) and capped at `max`, e.g. 1s, 2s, 4s,
+    /// 8s, 8s... with a 1 second interval and an 8 second cap, so a slow
+    /// zone is not queried hundreds of times.
+    /// [`ttl_interval`](Self::ttl_interval) still takes over when a TTL is
+    /// known.
+    pub fn backoff(mut self, max: Duration) -> Self {
+        self.backoff = Some(max);
+        self
+    }
+
+    /// Give up with [`Error::PropagationTimeout`] after `attempts` polls,
+    /// even if the [`timeout`](Self::timeout) has not passed yet.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Only accept DNSSEC-validated answers, failing straight away with
+    /// [`Error::DnssecValidation`] on one that is not, since a forged TXT
+    /// answer would defeat the challenge.
+    ///
+    /// Lookups go through [`DnsResolver::lookup_txt_validated`]; the
+    /// built-in resolvers need the `dnssec` feature. The zone must be
+    /// signed, and the servers asked must be able to serve the chain of
+    /// trust, so this suits [`PropagationMode::Resolver`] and
+    /// [`PropagationMode::PublicResolvers`] better than
+    /// [`PropagationMode::AllNameservers`].
+    pub fn require_dnssec(mut self, require: bool) -> Self {
+        self.require_dnssec = require;
+        self
+    }
+
+    /// Reach the nameservers asked in [`PropagationMode::AllNameservers`]
+    /// and [`PropagationMode::PublicResolvers`] only over `family`, e.g.
+    /// [`IpFamily::V6`] to check the same path as a CA that validates over
+    /// IPv6. Defaults to [`IpFamily::Any`]. Has no effect in
+    /// [`PropagationMode::Resolver`].
+    ///
+    /// With [`IpFamily::V6`] the default public resolvers are asked at
+    /// their IPv6 addresses; resolvers set with
+    /// [`public_resolvers`](Self::public_resolvers) must be IPv6 addresses
+    /// or host names with AAAA records.
+    pub fn ip_family(mut self, family: IpFamily) -> Self {
+        self.ip_family = family;
+        self
+    }
+
+    /// How long to wait after poll number `attempt`, given the lowest TTL
+    /// seen.
+    fn poll_interval(&self, attempt: u32, ttl: Option<Duration>) -> Duration {
+        match (self.ttl_interval, ttl, self.backoff) {
+            (Some((min, max)), Some(ttl), _) => (ttl + TTL_MARGIN).clamp(min, max),
+            (_, _, Some(max)) => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                self.interval.saturating_mul(factor).min(max)
+            }
+            _ => self.interval,
+        }
+    }
+
+    /// The resolvers [`PropagationMode::PublicResolvers`] asks: those set
+    /// with [`public_resolvers`](Self::public_resolvers), or the defaults
+    /// for [`ip_family`](Self::ip_family).
+    fn public_resolver_hosts(&self) -> Vec<String> {
+        match (&self.public_resolvers, self.ip_family) {
+            (Some(hosts), _) => hosts.clone(),
+            (None, IpFamily::V6) => PUBLIC_RESOLVERS_V6.map(String::from).to_vec(),
+            (None, _) => PUBLIC_RESOLVERS.map(String::from).to_vec(),
+        }
+    }
+
+    /// Call `f` after every poll, including the last one, e.g. to show
+    /// which nameservers are still behind.
+    pub fn on_progress(mut self, f: impl Fn(&PropagationProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(ProgressCallback(Arc::new(f)));
+        self
+    }
+}
+
+impl AcmeDnsClient {
+    /// Poll DNS until `creds.fulldomain` serves `expected` as one of its TXT
+    /// values, e.g. after [`update_txt`](Self::update_txt) and before asking
+    /// the CA to validate.
+    ///
+    /// Returns [`Error::PropagationTimeout`] with the last answer from each
+    /// server if the value does not show up on enough of them (see
+    /// [`PropagationOptions::quorum`]) within the timeout. Failing to find
+    /// the servers to ask, e.g. the zone's nameservers in
+    /// [`PropagationMode::AllNameservers`], is reported straight away
+    /// instead, as is an empty
+    /// [`public_resolvers`](PropagationOptions::public_resolvers) list.
+    pub async fn wait_for_propagation(
+        &self,
+        creds: &Credentials,
+        expected: &str,
+        options: &PropagationOptions,
+    ) -> Result<(), Error> {
+        self.wait_for_propagation_all(creds, &[expected], options)
+            .await
+    }
+
+    /// Like [`wait_for_propagation`](Self::wait_for_propagation), but a
+    /// server only counts once it serves every value in `expected` at the
+    /// same time, e.g. the two challenge values of a certificate for both
+    /// `example.com` and `*.example.com`.
+    pub async fn wait_for_propagation_all(
+        &self,
+        creds: &Credentials,
+        expected: &[&str],
+        options: &PropagationOptions,
+    ) -> Result<(), Error> {
+        let name = creds.fulldomain.trim_end_matches('.');
+        let servers = match options.mode {
+            PropagationMode::Resolver => vec![("resolver".to_string(), self.resolver.clone())],
+            PropagationMode::AllNameservers => {
+                let zone = creds
+                    .zone()
+                    .unwrap_or_else(|| name.split_once('.').map_or(name, |(_, zone)| zone));
+                self.zone_nameservers(zone, options.ip_family).await?
+            }
+            PropagationMode::PublicResolvers => {
+                let hosts = options.public_resolver_hosts();
+                let mut servers = Vec::with_capacity(hosts.len());
+                for host in hosts {
+                    let resolver = self
+                        .resolver
+                        .nameserver_over(&host, options.ip_family)
+                        .await?;
+                    servers.push((host, resolver));
+                }
+                servers
+            }
+        };
+        if servers.is_empty() {
+            return Err(Error::Dns(format!("no servers to ask for {name}")));
+        }
+        let quorum = options
+            .quorum
+            .map_or(servers.len(), |q| q.min(servers.len()));
+
+        let start = Instant::now();
+        let deadline = start + options.timeout;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            // Ask every server at once, giving up on each when the deadline
+            // passes, so slow or unreachable ones cannot stretch the wait.
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let lookups = servers.iter().map(|(_, resolver)| async move {
+                let lookup = lookup_txt_or_empty(resolver.as_ref(), name, options.require_dnssec);
+                let timeout = timer::sleep(remaining);
+                match future::select(std::pin::pin!(lookup), std::pin::pin!(timeout)).await {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => Err(Error::Timeout),
+                }
+            });
+            let results = future::join_all(lookups).await;
+
+            let mut answers = Vec::with_capacity(servers.len());
+            let mut ttl: Option<Duration> = None;
+            for ((server, _), result) in servers.iter().zip(results) {
+                let txt = match result {
+                    Ok((values, record_ttl)) => {
+                        ttl = ttl.into_iter().chain(record_ttl).min();
+                        Ok(values)
+                    }
+                    Err(e @ Error::DnssecValidation(_)) => return Err(e),
+                    Err(e) => Err(e.to_string()),
+                };
+                answers.push(ServerAnswer {
+                    server: server.clone(),
+                    txt,
+                });
+            }
+            let interval = options.poll_interval(attempt, ttl);
+
+            let served = |answer: &ServerAnswer| {
+                answer
+                    .txt
+                    .as_ref()
+                    .is_ok_and(|values| expected.iter().all(|e| values.iter().any(|v| v == e)))
+            };
+            if let Some(ProgressCallback(on_progress)) = &options.on_progress {
+                let (done, pending): (Vec<_>, Vec<_>) = answers.iter().partition(|a| served(a));
+                let names = |answers: Vec<&ServerAnswer>| {
+                    answers.into_iter().map(|a| a.server.clone()).collect()
+                };
+                on_progress(&PropagationProgress {
+                    attempt,
+                    elapsed: start.elapsed(),
+                    served: names(done),
+                    pending: names(pending),
+                    next_interval: interval,
+                });
+            }
+            if answers.iter().filter(|a| served(a)).count() >= quorum {
+                return Ok(());
+            }
+            let out_of_attempts = options.max_attempts.is_some_and(|max| attempt >= max);
+            if out_of_attempts || Instant::now() + interval > deadline {
+                return Err(Error::PropagationTimeout {
+                    name: name.to_string(),
+                    answers,
+                });
+            }
+            timer::sleep(interval).await;
+        }
+    }
+
+    /// Set `txt` and wait for it to propagate as
+    /// [`wait_for_propagation`](Self::wait_for_propagation) does with
+    /// `options`, putting the previous TXT values back if it does not.
+    ///
+    /// The values served before the update are read with
+    /// [`current_txt`](Self::current_txt). acme-dns keeps the last two
+    /// values, so on failure two are sent with
+    /// [`update_txt`](Self::update_txt) to push the new one out: the
+    /// previous two, a single previous value twice, or, if there was none,
+    /// a fixed placeholder twice. The failure is then reported as
+    /// [`Error::RolledBack`] with the values now served, or
+    /// [`Error::RollbackFailed`] if restoring failed too. An error from the
+    /// initial update is returned as is, since nothing changed.
+    pub async fn update_txt_transactional(
+        &self,
+        creds: &Credentials,
+        txt: &str,
+        options: &PropagationOptions,
+    ) -> Result<(), Error> {
+        let mut previous = self.current_txt(creds).await?;
+        self.update_txt(creds, txt).await?;
+
+        let Err(cause) = self.wait_for_propagation(creds, txt, options).await else {
+            return Ok(());
+        };
+
+        previous.drain(..previous.len().saturating_sub(2));
+        if previous.is_empty() {
+            previous.push(crate::PRIME_TXT.to_string());
+        }
+        let resend = match previous.as_slice() {
+            [only] => vec![only.clone(), only.clone()],
+            values => values.to_vec(),
+        };
+
+        let cause = Box::new(cause);
+        for value in &resend {
+            if let Err(rollback) = self.update_txt(creds, value).await {
+                return Err(Error::RollbackFailed {
+                    cause,
+                    rollback: Box::new(rollback),
+                });
+            }
+        }
+        Err(Error::RolledBack {
+            cause,
+            restored: previous,
+        })
+    }
+
+    /// Resolvers for each authoritative nameserver of `zone`.
+    async fn zone_nameservers(
+        &self,
+        zone: &str,
+        family: IpFamily,
+    ) -> Result<Vec<(String, Arc<dyn DnsResolver>)>, Error> {
+        let hosts = self.resolver.lookup_ns(zone).await?;
+        if hosts.is_empty() {
+            return Err(Error::Dns(format!("no NS records for {zone}")));
+        }
+
+        let mut servers = Vec::with_capacity(hosts.len());
+        for host in hosts {
+            let resolver = self.resolver.nameserver_over(&host, family).await?;
+            servers.push((host, resolver));
+        }
+        Ok(servers)
+    }
+}
+
+/// Like [`DnsResolver::lookup_txt_with_ttl`] (or
+/// [`lookup_txt_validated`](DnsResolver::lookup_txt_validated) with
+/// `dnssec`), but a name that does not exist yet is just not propagated.
+async fn lookup_txt_or_empty(
+    resolver: &dyn DnsResolver,
+    name: &str,
+    dnssec: bool,
+) -> Result<TxtWithTtl, Error> {
+    let lookup = if dnssec {
+        resolver.lookup_txt_validated(name)
+    } else {
+        resolver.lookup_txt_with_ttl(name)
+    };
+    match lookup.await {
+        Err(Error::NxDomain(_)) => Ok((Vec::new(), None)),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoxFuture;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// A nameserver whose TXT answers advance one step per query, sticking
+    /// at the last one.
+    #[derive(Debug)]
+    struct StubNameserver {
+        answers: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl StubNameserver {
+        fn new(steps: &[&[&str]]) -> Arc<Self> {
+            let answers = steps
+                .iter()
+                .rev()
+                .map(|step| step.iter().map(|v| v.to_string()).collect())
+                .collect();
+            Arc::new(Self {
+                answers: Mutex::new(answers),
+            })
+        }
+    }
+
+    impl DnsResolver for StubNameserver {
+        fn lookup_txt<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            let mut answers = self.answers.lock().unwrap();
+            let answer = if answers.len() > 1 {
+                answers.pop().unwrap()
+            } else {
+                answers[0].clone()
+            };
+            Box::pin(async move { Ok(answer) })
+        }
+    }
+
+    /// Resolver for the `auth.example.org` zone, delegated to `nameservers`.
+    #[derive(Debug)]
+    struct StubZone {
+        nameservers: HashMap<String, Arc<StubNameserver>>,
+    }
+
+    impl StubZone {
+        fn new(nameservers: &[(&str, Arc<StubNameserver>)]) -> Self {
+            Self {
+                nameservers: nameservers
+                    .iter()
+                    .map(|(host, ns)| (host.to_string(), ns.clone()))
+                    .collect(),
+            }
+        }
+    }
+
+    impl DnsResolver for StubZone {
+        fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Err(Error::NxDomain(name.to_string())) })
+        }
+
+        fn lookup_ns<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            assert_eq!(zone, "auth.example.org");
+            let mut hosts: Vec<_> = self.nameservers.keys().cloned().collect();
+            hosts.sort();
+            Box::pin(async move { Ok(hosts) })
+        }
+
+        fn nameserver<'a>(
+            &'a self,
+            host: &'a str,
+        ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+            let ns: Arc<dyn DnsResolver> = self.nameservers[host].clone();
+            Box::pin(async move { Ok(ns) })
+        }
+
+        fn nameserver_over<'a>(
+            &'a self,
+            host: &'a str,
+            _: IpFamily,
+        ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+            self.nameserver(host)
+        }
+    }
+
+    /// A zone with one nameserver that answers differently over IPv4 and
+    /// IPv6, recording which family each lookup asked for.
+    #[derive(Debug)]
+    struct DualStackZone {
+        v4: Arc<StubNameserver>,
+        v6: Arc<StubNameserver>,
+        asked: Arc<Mutex<Vec<IpFamily>>>,
+    }
+
+    impl DnsResolver for DualStackZone {
+        fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Err(Error::NxDomain(name.to_string())) })
+        }
+
+        fn lookup_ns<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Ok(vec!["ns1.example.org".to_string()]) })
+        }
+
+        fn nameserver_over<'a>(
+            &'a self,
+            _: &'a str,
+            family: IpFamily,
+        ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+            self.asked.lock().unwrap().push(family);
+            let ns: Arc<dyn DnsResolver> = match family {
+                IpFamily::V6 => self.v6.clone(),
+                _ => self.v4.clone(),
+            };
+            Box::pin(async move { Ok(ns) })
+        }
+    }
+
+    #[tokio::test]
+    async fn ip_family_picks_the_nameserver_path() {
+        let asked = Arc::default();
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(DualStackZone {
+                v4: StubNameserver::new(&[&["token123"]]),
+                v6: StubNameserver::new(&[&["old"]]),
+                asked: Arc::clone(&asked),
+            })
+            .build()
+            .unwrap();
+
+        client
+            .wait_for_propagation(&creds(), "token123", &fast())
+            .await
+            .unwrap();
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &fast().ip_family(IpFamily::V6))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::PropagationTimeout { .. }),
+            "got {err:?}"
+        );
+        assert_eq!(*asked.lock().unwrap(), [IpFamily::Any, IpFamily::V6]);
+    }
+
+    fn creds() -> Credentials {
+        Credentials {
+            username: "user-uuid".into(),
+            password: "pw".into(),
+            subdomain: "8e57".into(),
+            fulldomain: "8e57.auth.example.org".into(),
+            allowfrom: vec![],
+        }
+    }
+
+    fn fast() -> PropagationOptions {
+        PropagationOptions::new()
+            .interval(Duration::from_millis(1))
+            .timeout(Duration::from_millis(200))
+            .mode(PropagationMode::AllNameservers)
+    }
+
+    #[tokio::test]
+    async fn resolver_mode_polls_the_client_resolver() {
+        let ns = StubNameserver::new(&[&[], &["token123"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(Arc::into_inner(ns).unwrap())
+            .build()
+            .unwrap();
+
+        let options = fast().mode(PropagationMode::Resolver);
+        client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn all_nameservers_waits_for_the_slowest() {
+        let ns1 = StubNameserver::new(&[&["token123"]]);
+        let ns2 = StubNameserver::new(&[&[], &["old"], &["old", "token123"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                ("ns1.example.org", ns1),
+                ("ns2.example.org", ns2.clone()),
+            ]))
+            .build()
+            .unwrap();
+
+        client
+            .wait_for_propagation(&creds(), "token123", &fast())
+            .await
+            .unwrap();
+        assert_eq!(
+            ns2.answers.lock().unwrap().len(),
+            1,
+            "ns2 polled to the end"
+        );
+    }
+
+    #[tokio::test]
+    async fn all_values_must_be_served_together() {
+        let ns = StubNameserver::new(&[&["token123"], &["token456", "token123"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[("ns1.example.org", ns.clone())]))
+            .build()
+            .unwrap();
+
+        client
+            .wait_for_propagation_all(&creds(), &["token123", "token456"], &fast())
+            .await
+            .unwrap();
+        assert_eq!(ns.answers.lock().unwrap().len(), 1, "polled until both");
+
+        let ns = StubNameserver::new(&[&["token123"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[("ns1.example.org", ns)]))
+            .build()
+            .unwrap();
+        let err = client
+            .wait_for_propagation_all(&creds(), &["token123", "token456"], &fast())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::PropagationTimeout { .. }),
+            "got {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn progress_reports_each_poll() {
+        let ns1 = StubNameserver::new(&[&["token123"]]);
+        let ns2 = StubNameserver::new(&[&[], &["old"], &["token123"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                ("ns1.example.org", ns1),
+                ("ns2.example.org", ns2),
+            ]))
+            .build()
+            .unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let options = fast().on_progress(move |p| recorder.lock().unwrap().push(p.clone()));
+        client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap();
+
+        let seen = seen.lock().unwrap();
+        let attempts: Vec<_> = seen.iter().map(|p| p.attempt).collect();
+        assert_eq!(attempts, vec![1, 2, 3]);
+        assert_eq!(seen[0].served, vec!["ns1.example.org"]);
+        assert_eq!(seen[0].pending, vec!["ns2.example.org"]);
+        assert_eq!(seen[2].pending, Vec::<String>::new());
+        assert!(seen.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    }
+
+    /// Serves `values` with a fixed TTL.
+    #[derive(Debug)]
+    struct TtlStub {
+        values: Vec<String>,
+        ttl: Duration,
+    }
+
+    impl DnsResolver for TtlStub {
+        fn lookup_txt<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Ok(self.values.clone()) })
+        }
+
+        fn lookup_txt_with_ttl<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> BoxFuture<'a, Result<TxtWithTtl, Error>> {
+            Box::pin(async move { Ok((self.values.clone(), Some(self.ttl))) })
+        }
+    }
+
+    #[test]
+    fn ttl_interval_is_ttl_plus_margin_within_bounds() {
+        let options =
+            PropagationOptions::new().ttl_interval(Duration::from_secs(5), Duration::from_secs(60));
+        let secs = |s| Some(Duration::from_secs(s));
+
+        assert_eq!(options.poll_interval(1, secs(30)), Duration::from_secs(31));
+        assert_eq!(options.poll_interval(1, secs(300)), Duration::from_secs(60));
+        assert_eq!(options.poll_interval(1, secs(0)), Duration::from_secs(5));
+        assert_eq!(options.poll_interval(1, None), Duration::from_secs(5));
+        assert_eq!(
+            PropagationOptions::new().poll_interval(1, secs(30)),
+            Duration::from_secs(5),
+            "fixed interval unless enabled"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_the_interval_up_to_the_cap() {
+        let options = PropagationOptions::new()
+            .interval(Duration::from_secs(1))
+            .backoff(Duration::from_secs(8));
+
+        let schedule: Vec<_> = (1..=6)
+            .map(|attempt| options.poll_interval(attempt, None).as_secs())
+            .collect();
+        assert_eq!(schedule, [1, 2, 4, 8, 8, 8]);
+        assert_eq!(
+            options.poll_interval(u32::MAX, None),
+            Duration::from_secs(8)
+        );
+    }
+
+    #[tokio::test]
+    async fn max_attempts_stops_before_the_timeout() {
+        let ns = StubNameserver::new(&[&["old"]]);
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[("ns1.example.org", ns)]))
+            .build()
+            .unwrap();
+        let intervals = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&intervals);
+
+        let options = fast()
+            .timeout(Duration::from_secs(30))
+            .backoff(Duration::from_millis(4))
+            .max_attempts(4)
+            .on_progress(move |p| seen.lock().unwrap().push(p.next_interval.as_millis()));
+        let started = std::time::Instant::now();
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::PropagationTimeout { .. }),
+            "got {err:?}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(*intervals.lock().unwrap(), [1, 2, 4, 4]);
+    }
+
+    #[tokio::test]
+    async fn ttl_interval_uses_the_resolver_ttl() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(TtlStub {
+                values: vec!["old".into()],
+                ttl: Duration::from_secs(30),
+            })
+            .build()
+            .unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let options = PropagationOptions::new()
+            .interval(Duration::from_secs(60))
+            .ttl_interval(Duration::ZERO, Duration::from_secs(45))
+            .timeout(Duration::from_secs(10))
+            .on_progress(move |p| recorder.lock().unwrap().push(p.next_interval));
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::PropagationTimeout { .. }), "{err:?}");
+        assert_eq!(*seen.lock().unwrap(), vec![Duration::from_secs(31)]);
+    }
+
+    #[tokio::test]
+    async fn require_dnssec_rejects_unvalidated_answers() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(Arc::into_inner(StubNameserver::new(&[&["token123"]])).unwrap())
+            .build()
+            .unwrap();
+
+        let options = fast().mode(PropagationMode::Resolver);
+        client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap();
+
+        let options = options
+            .timeout(Duration::from_secs(60))
+            .require_dnssec(true);
+        let started = Instant::now();
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DnssecValidation(_)), "got {err:?}");
+        assert_eq!(err.kind(), crate::ErrorKind::Dns);
+        assert!(started.elapsed() < Duration::from_secs(5), "fails fast");
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_the_wait_promptly() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(Arc::into_inner(StubNameserver::new(&[&["old"]])).unwrap())
+            .build()
+            .unwrap();
+
+        let options = PropagationOptions::new()
+            .interval(Duration::from_millis(10))
+            .timeout(Duration::from_secs(60));
+        let started = Instant::now();
+        let err = crate::cancellable(
+            tokio::time::sleep(Duration::from_millis(50)),
+            client.wait_for_propagation(&creds(), "token123", &options),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err, Error::Cancelled), "got {err:?}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    fn public_resolvers(lagging: Arc<StubNameserver>) -> AcmeDnsClient {
+        AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                ("1.1.1.1", StubNameserver::new(&[&["token123"]])),
+                ("8.8.8.8", StubNameserver::new(&[&[], &["token123"]])),
+                ("9.9.9.9", lagging),
+            ]))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn public_resolvers_quorum_tolerates_a_laggard() {
+        let client = public_resolvers(StubNameserver::new(&[&["old"]]));
+
+        let options = fast().mode(PropagationMode::PublicResolvers).quorum(2);
+        client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn public_resolvers_default_quorum_is_all() {
+        let client = public_resolvers(StubNameserver::new(&[&["old"]]));
+
+        let options = fast().mode(PropagationMode::PublicResolvers);
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+
+        let Error::PropagationTimeout { answers, .. } = &err else {
+            panic!("expected PropagationTimeout, got {err:?}");
+        };
+        let disagreeing: Vec<_> = answers
+            .iter()
+            .filter(|a| a.txt != Ok(vec!["token123".to_string()]))
+            .map(|a| a.server.as_str())
+            .collect();
+        assert_eq!(disagreeing, vec!["9.9.9.9"]);
+    }
+
+    #[tokio::test]
+    async fn ipv6_defaults_to_public_resolvers_ipv6_addresses() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                (
+                    "2606:4700:4700::1111",
+                    StubNameserver::new(&[&["token123"]]),
+                ),
+                (
+                    "2001:4860:4860::8888",
+                    StubNameserver::new(&[&["token123"]]),
+                ),
+                ("2620:fe::fe", StubNameserver::new(&[&["token123"]])),
+            ]))
+            .build()
+            .unwrap();
+
+        let options = fast()
+            .mode(PropagationMode::PublicResolvers)
+            .ip_family(IpFamily::V6);
+        client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap();
+    }
+
+    /// A nameserver that never answers.
+    #[derive(Debug)]
+    struct Unresponsive;
+
+    impl DnsResolver for Unresponsive {
+        fn lookup_txt<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(future::pending())
+        }
+    }
+
+    /// Public resolvers where `"fast"` serves the value at once and every
+    /// other host hangs.
+    #[derive(Debug)]
+    struct HangingResolvers;
+
+    impl DnsResolver for HangingResolvers {
+        fn lookup_txt<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>, Error>> {
+            Box::pin(async move { Err(Error::NxDomain(name.to_string())) })
+        }
+
+        fn nameserver<'a>(
+            &'a self,
+            host: &'a str,
+        ) -> BoxFuture<'a, Result<Arc<dyn DnsResolver>, Error>> {
+            let ns: Arc<dyn DnsResolver> = match host {
+                "fast" => StubNameserver::new(&[&["token123"]]),
+                _ => Arc::new(Unresponsive),
+            };
+            Box::pin(async move { Ok(ns) })
+        }
+    }
+
+    #[tokio::test]
+    async fn hanging_nameservers_do_not_outlast_the_timeout() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(HangingResolvers)
+            .build()
+            .unwrap();
+
+        let options = fast()
+            .mode(PropagationMode::PublicResolvers)
+            .public_resolvers(["slow-1", "slow-2", "fast"]);
+        let started = Instant::now();
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+        assert!(
+            started.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            started.elapsed()
+        );
+
+        let Error::PropagationTimeout { answers, .. } = &err else {
+            panic!("expected PropagationTimeout, got {err:?}");
+        };
+        let served: Vec<_> = answers
+            .iter()
+            .map(|a| (a.server.as_str(), a.txt.is_ok()))
+            .collect();
+        assert_eq!(
+            served,
+            [("slow-1", false), ("slow-2", false), ("fast", true)]
+        );
+
+        client
+            .wait_for_propagation(&creds(), "token123", &options.quorum(1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_public_resolvers_is_an_error() {
+        let client = public_resolvers(StubNameserver::new(&[&["token123"]]));
+
+        let options = fast()
+            .mode(PropagationMode::PublicResolvers)
+            .public_resolvers(Vec::<String>::new());
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Dns(_)), "got {err:?}");
+    }
+
+    #[tokio::test]
+    async fn quorum_of_zero_still_needs_one_server() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                ("1.1.1.1", StubNameserver::new(&[&["old"]])),
+                ("8.8.8.8", StubNameserver::new(&[&[]])),
+                ("9.9.9.9", StubNameserver::new(&[&["old"]])),
+            ]))
+            .build()
+            .unwrap();
+
+        let options = fast().mode(PropagationMode::PublicResolvers).quorum(0);
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::PropagationTimeout { .. }),
+            "got {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn transactional_update_rolls_back_on_timeout() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start_async().await;
+        let update = |txt: &'static str| {
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path("/update")
+                    .json_body(serde_json::json!({"subdomain": "8e57", "txt": txt}));
+                then.status(200);
+            })
+        };
+        let new = update("token123");
+        let rollback = update("old");
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .resolver(Arc::into_inner(StubNameserver::new(&[&["old"]])).unwrap())
+            .build()
+            .unwrap();
+        let options = fast().mode(PropagationMode::Resolver);
+        let err = client
+            .update_txt_transactional(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+
+        let Error::RolledBack { cause, restored } = &err else {
+            panic!("expected RolledBack, got {err:?}");
+        };
+        assert!(
+            matches!(**cause, Error::PropagationTimeout { .. }),
+            "{cause:?}"
+        );
+        assert_eq!(restored, &vec!["old".to_string()]);
+        new.assert_hits(1);
+        rollback.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn transactional_update_without_previous_value_sends_placeholder() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start_async().await;
+        let update = |txt: &'static str| {
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path("/update")
+                    .json_body(serde_json::json!({"subdomain": "8e57", "txt": txt}));
+                then.status(200);
+            })
+        };
+        let new = update("token123");
+        let placeholder = update(crate::PRIME_TXT);
+
+        let client = AcmeDnsClient::builder(server.base_url())
+            .resolver(Arc::into_inner(StubNameserver::new(&[&[]])).unwrap())
+            .build()
+            .unwrap();
+        let options = fast().mode(PropagationMode::Resolver);
+        let err = client
+            .update_txt_transactional(&creds(), "token123", &options)
+            .await
+            .unwrap_err();
+
+        let Error::RolledBack { restored, .. } = &err else {
+            panic!("expected RolledBack, got {err:?}");
+        };
+        assert_eq!(restored, &vec![crate::PRIME_TXT.to_string()]);
+        new.assert_hits(1);
+        placeholder.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn timeout_reports_each_nameserver() {
+        let client = AcmeDnsClient::builder("http://localhost")
+            .resolver(StubZone::new(&[
+                ("ns1.example.org", StubNameserver::new(&[&["token123"]])),
+                ("ns2.example.org", StubNameserver::new(&[&["old"]])),
+            ]))
+            .build()
+            .unwrap();
+
+        let err = client
+            .wait_for_propagation(&creds(), "token123", &fast())
+            .await
+            .unwrap_err();
+
+        let Error::PropagationTimeout { name, answers } = &err else {
+            panic!("expected PropagationTimeout, got {err:?}");
+        };
+        assert_eq!(name, "8e57.auth.example.org");
+        assert_eq!(
+            answers,
+            &vec![
+                ServerAnswer {
+                    server: "ns1.example.org".into(),
+                    txt: Ok(vec!["token123".into()]),
+                },
+                ServerAnswer {
+                    server: "ns2.example.org".into(),
+                    txt: Ok(vec!["old".into()]),
+                },
+            ]
+        );
+        assert!(err.to_string().contains("ns2.example.org served [old]"));
+    }
+}
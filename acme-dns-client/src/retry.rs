@@ -0,0 +1,294 @@
+//! Retry policy for transient HTTP failures.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::time::{Duration, UNIX_EPOCH};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use web_time::SystemTime;
+
+/// How many times to retry a request, and how long to wait in between.
+///
+/// Requests are retried on timeouts, connection failures, `429 Too Many
+/// Requests` and `5xx` responses. When the server sends `Retry-After`, that
+/// delay is used; otherwise the delay doubles from `initial_delay` on each
+/// attempt, capped at `max_delay`.
+///
+/// Retrying is off unless a policy is set with
+/// [`AcmeDnsClientBuilder::retry`](crate::AcmeDnsClientBuilder::retry). For
+/// other strategies, see [`Backoff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; `0` disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound for the exponential backoff.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times with the default backoff.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// Backoff delay before retry number `attempt` (starting at 0).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three retries, starting at 500ms and capped at 30s.
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Decides how long to wait before each retry.
+///
+/// A fresh copy (via `Clone`) is used for every request, so implementations
+/// may keep per-request state such as the previous delay. A `Retry-After`
+/// header from the server takes precedence over the returned delay, but
+/// returning `None` always stops retrying.
+pub trait Backoff: fmt::Debug + Send + Sync {
+    /// Delay before retry number `attempt` (starting at 0), or `None` to
+    /// give up.
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration>;
+}
+
+/// Exponential backoff as described by a [`RetryPolicy`]; the strategy
+/// [`AcmeDnsClientBuilder::retry`](crate::AcmeDnsClientBuilder::retry) uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExponentialBackoff {
+    policy: RetryPolicy,
+}
+
+impl ExponentialBackoff {
+    /// Back off according to `policy`.
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl From<RetryPolicy> for ExponentialBackoff {
+    fn from(policy: RetryPolicy) -> Self {
+        Self::new(policy)
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        (attempt < self.policy.max_retries).then(|| self.policy.backoff(attempt))
+    }
+}
+
+/// "Decorrelated jitter" backoff: each delay is picked at random between
+/// `base` and three times the previous delay, capped at `cap`.
+///
+/// Spreads out retries from many clients that failed at the same moment,
+/// where plain exponential backoff would have them all retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct DecorrelatedJitter {
+    max_retries: u32,
+    base: Duration,
+    cap: Duration,
+    prev: Duration,
+    rng: u64,
+}
+
+impl DecorrelatedJitter {
+    /// Retry up to `max_retries` times, with delays between `base` and `cap`.
+    pub fn new(max_retries: u32, base: Duration, cap: Duration) -> Self {
+        Self::with_seed(max_retries, base, cap, random_u64())
+    }
+
+    /// Like [`new`](Self::new), but with a fixed random seed for
+    /// reproducible delays.
+    pub fn with_seed(max_retries: u32, base: Duration, cap: Duration, seed: u64) -> Self {
+        Self {
+            max_retries,
+            base,
+            cap,
+            prev: base,
+            // xorshift gets stuck at zero.
+            rng: seed | 1,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+impl Backoff for DecorrelatedJitter {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let low = self.base.as_millis() as u64;
+        let high = (self.prev.as_millis() as u64).saturating_mul(3).max(low);
+        let millis = low + self.next_random() % (high - low + 1);
+        self.prev = Duration::from_millis(millis).min(self.cap);
+        Some(self.prev)
+    }
+}
+
+/// Hands out a fresh [`Backoff`] for each request.
+pub(crate) trait NewBackoff: fmt::Debug + Send + Sync {
+    fn fresh(&self) -> Box<dyn Backoff>;
+}
+
+impl<B: Backoff + Clone + 'static> NewBackoff for B {
+    fn fresh(&self) -> Box<dyn Backoff> {
+        Box::new(self.clone())
+    }
+}
+
+/// Parse `Retry-After`, in either its delay-seconds or HTTP-date form.
+///
+/// A date in the past yields a zero delay.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    // Compare via the epoch: `httpdate` yields a std `SystemTime`, but "now"
+    // has to come from `web_time` to work on wasm32.
+    let at = httpdate::parse_http_date(value).ok()?;
+    let at = at.duration_since(UNIX_EPOCH).ok()?;
+    let now = SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Some(at.saturating_sub(now))
+}
+
+/// A random `u64`, from the per-instance keys of std's `RandomState`; good
+/// enough for jitter without pulling in a `rand` dependency.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().hash_one(0u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+
+        let delays: Vec<_> = (0..5).map(|n| policy.backoff(n).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn exponential_backoff_stops_after_max_retries() {
+        let mut backoff = ExponentialBackoff::new(RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(3),
+        });
+
+        let delays: Vec<_> = (0..5).map(|n| backoff.next_delay(n)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(3)),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn decorrelated_jitter_stays_within_bounds() {
+        let base = Duration::from_millis(100);
+        let cap = Duration::from_secs(2);
+        let mut backoff = DecorrelatedJitter::with_seed(20, base, cap, 42);
+
+        let mut prev = base;
+        for attempt in 0..20 {
+            let delay = backoff.next_delay(attempt).unwrap();
+            assert!(
+                delay >= base && delay <= cap,
+                "attempt {attempt}: {delay:?}"
+            );
+            assert!(
+                delay <= prev * 3,
+                "attempt {attempt}: {delay:?} after {prev:?}"
+            );
+            prev = delay;
+        }
+        assert_eq!(backoff.next_delay(20), None);
+    }
+
+    #[test]
+    fn decorrelated_jitter_is_reproducible_with_a_seed() {
+        let delays = |seed| {
+            let mut backoff = DecorrelatedJitter::with_seed(
+                5,
+                Duration::from_millis(100),
+                Duration::from_secs(10),
+                seed,
+            );
+            (0..5).map(|n| backoff.next_delay(n)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(delays(7), delays(7));
+        assert_ne!(delays(7), delays(8));
+    }
+
+    #[test]
+    fn retry_after_parses_seconds() {
+        assert_eq!(retry_after(&headers("2")), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn retry_after_parses_http_date() {
+        let at = SystemTime::now() + Duration::from_secs(120);
+        let delay = retry_after(&headers(&httpdate::fmt_http_date(at))).unwrap();
+
+        // HTTP dates only have second resolution.
+        assert!(delay > Duration::from_secs(118) && delay <= Duration::from_secs(120));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn retry_after_missing_or_garbage_is_none() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! Periodic TXT updates for renewal daemons.
+
+use std::fmt;
+use std::time::Duration;
+
+use futures_util::future;
+
+use crate::retry::random_u64;
+use crate::{AcmeDnsClient, Credentials, timer};
+
+/// Runs [`update_txt`](AcmeDnsClient::update_txt) for many accounts, each on
+/// its own interval.
+///
+/// Every wait is stretched by a random jitter (by default up to a tenth of
+/// the interval, see [`jitter`](Self::jitter)), and the first update of each
+/// account is delayed by the same amount, so accounts added together do
+/// not hit the server in lockstep.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use acme_dns_client::{AcmeDnsClient, Credentials, RenewalScheduler};
+/// # async fn run(client: AcmeDnsClient, creds: Credentials) {
+/// let mut scheduler = RenewalScheduler::new(client, |creds: &Credentials| {
+///     format!("txt-for-{}", creds.subdomain)
+/// });
+/// scheduler.add(creds, Duration::from_secs(3600));
+/// scheduler.run().await;
+/// # }
+/// ```
+pub struct RenewalScheduler<F> {
+    client: AcmeDnsClient,
+    txt: F,
+    jitter: Option<Duration>,
+    jobs: Vec<(Credentials, Duration)>,
+}
+
+impl<F> fmt::Debug for RenewalScheduler<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenewalScheduler")
+            .field("client", &self.client)
+            .field("jitter", &self.jitter)
+            .field("jobs", &self.jobs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> RenewalScheduler<F>
+where
+    F: Fn(&Credentials) -> String,
+{
+    /// Update through `client`, asking `txt` for each account's value every
+    /// time it is due.
+    pub fn new(client: AcmeDnsClient, txt: F) -> Self {
+        Self {
+            client,
+            txt,
+            jitter: None,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Update `creds` every `interval` (plus jitter).
+    pub fn add(&mut self, creds: Credentials, interval: Duration) -> &mut Self {
+        self.jobs.push((creds, interval));
+        self
+    }
+
+    /// Stretch each wait by a random amount up to `max`, instead of up to a
+    /// tenth of the interval. `Duration::ZERO` disables jitter.
+    pub fn jitter(&mut self, max: Duration) -> &mut Self {
+        self.jitter = Some(max);
+        self
+    }
+
+    /// Run the updates until the returned future is dropped.
+    ///
+    /// A failed update is logged with `tracing` and retried at the next
+    /// interval; install a [`RetryPolicy`](crate::RetryPolicy) on the
+    /// client to retry sooner.
+    pub async fn run(&self) {
+        future::join_all(self.jobs.iter().map(|(creds, interval)| async move {
+            let max_jitter = self.jitter.unwrap_or(*interval / 10);
+            timer::sleep(random_up_to(max_jitter)).await;
+            loop {
+                let txt = (self.txt)(creds);
+                if let Err(e) = self.client.update_txt(creds, &txt).await {
+                    tracing::warn!(
+                        fulldomain = %creds.fulldomain,
+                        error = %e,
+                        "scheduled TXT update failed"
+                    );
+                }
+                timer::sleep(*interval + random_up_to(max_jitter)).await;
+            }
+        }))
+        .await;
+    }
+}
+
+/// A random duration between zero and `max`.
+fn random_up_to(max: Duration) -> Duration {
+    let millis = max.as_millis() as u64;
+    if millis == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(random_u64() % (millis + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn creds(subdomain: &str) -> Credentials {
+        Credentials {
+            username: "user-uuid".into(),
+            password: "pw".into(),
+            subdomain: subdomain.into(),
+            fulldomain: format!("{subdomain}.auth.example.org"),
+            allowfrom: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_each_account_repeatedly() {
+        let server = MockServer::start_async().await;
+        let update = |subdomain: &'static str| {
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path("/update")
+                    .json_body(serde_json::json!({
+                        "subdomain": subdomain,
+                        "txt": format!("txt-{subdomain}"),
+                    }));
+                then.status(200);
+            })
+        };
+        let first = update("one");
+        let second = update("two");
+
+        let client = AcmeDnsClient::new(server.base_url()).unwrap();
+        let mut scheduler = RenewalScheduler::new(client, |creds: &Credentials| {
+            format!("txt-{}", creds.subdomain)
+        });
+        scheduler
+            .jitter(Duration::from_millis(5))
+            .add(creds("one"), Duration::from_millis(20))
+            .add(creds("two"), Duration::from_millis(20));
+
+        let _ = tokio::time::timeout(Duration::from_millis(300), scheduler.run()).await;
+
+        assert!(first.hits() >= 3, "one: {} updates", first.hits());
+        assert!(second.hits() >= 3, "two: {} updates", second.hits());
+    }
+
+    #[test]
+    fn random_up_to_stays_in_range() {
+        assert_eq!(random_up_to(Duration::ZERO), Duration::ZERO);
+        for _ in 0..100 {
+            assert!(random_up_to(Duration::from_millis(10)) <= Duration::from_millis(10));
+        }
+    }
+}
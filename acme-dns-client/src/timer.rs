@@ -0,0 +1,48 @@
+//! Sleeping that works on both native targets and wasm32 (browser timers).
+//!
+//! Natively this is `futures-timer`, which needs no runtime, so the retry,
+//! health and propagation waits also run under async-std, smol, a plain
+//! `block_on`, or a tokio runtime built without `enable_time()` (where
+//! `tokio::time::sleep` would panic).
+
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    gloo_timers::future::sleep(duration).await;
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn sleep_works_without_a_tokio_runtime() {
+        let started = Instant::now();
+        async_std::task::block_on(sleep(Duration::from_millis(20)));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn sleep_works_inside_tokio() {
+        let started = Instant::now();
+        sleep(Duration::from_millis(20)).await;
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn sleep_works_in_tokio_without_the_time_driver() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        runtime.block_on(sleep(Duration::from_millis(20)));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,79 @@
+//! Runs the `acme-dns-cli` binary.
+
+use std::process::Command;
+
+fn cli() -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_acme-dns-cli"));
+    for var in [
+        "ACME_DNS_API_BASE",
+        "ACME_DNS_USERNAME",
+        "ACME_DNS_PASSWORD",
+        "ACME_DNS_SUBDOMAIN",
+        "ACME_DNS_FULLDOMAIN",
+        "ACME_DNS_ALLOWFROM",
+    ] {
+        cmd.env_remove(var);
+    }
+    cmd
+}
+
+#[test]
+fn config_redacts_the_password() {
+    let output = cli()
+        .arg("config")
+        .env("ACME_DNS_API_BASE", "https://auth.example.org/")
+        .env("ACME_DNS_USERNAME", "user-uuid")
+        .env("ACME_DNS_PASSWORD", "hunter2-secret")
+        .env("ACME_DNS_SUBDOMAIN", "8e57")
+        .env("ACME_DNS_FULLDOMAIN", "8e57.auth.example.org")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("api_base: https://auth.example.org/"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("credentials: environment"), "{stdout}");
+    assert!(
+        stdout.contains("fulldomain=8e57.auth.example.org"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("hunter2-secret"), "{stdout}");
+}
+
+#[test]
+fn config_json_from_file_redacts_the_password() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("creds.json");
+    std::fs::write(
+        &path,
+        r#"{"username":"user-uuid","password":"hunter2-secret",
+            "subdomain":"8e57","fulldomain":"8e57.auth.example.org"}"#,
+    )
+    .unwrap();
+
+    let output = cli()
+        .args(["config", "--json", "--creds"])
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let out: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(out["api_base"], serde_json::Value::Null);
+    assert_eq!(out["credentials"]["subdomain"], "8e57");
+    assert!(!stdout.contains("hunter2-secret"), "{stdout}");
+}
+
+#[test]
+fn completions_for_bash() {
+    let output = cli().args(["completions", "bash"]).output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("acme-dns-cli"), "{stdout}");
+    assert!(stdout.contains("verify-txt"), "{stdout}");
+}